@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE refresh_token DROP COLUMN access_jti;
+DROP TABLE revoked_token;
\ No newline at end of file
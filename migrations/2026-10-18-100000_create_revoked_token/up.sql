@@ -0,0 +1,11 @@
+CREATE TABLE revoked_token (
+    jti UUID PRIMARY KEY,
+    user_id UUID NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    CONSTRAINT fk_user_id FOREIGN KEY (user_id) REFERENCES "user" (id) ON DELETE CASCADE
+);
+
+CREATE INDEX revoked_token_expires_at_idx ON revoked_token (expires_at);
+
+ALTER TABLE refresh_token ADD COLUMN access_jti UUID;
\ No newline at end of file
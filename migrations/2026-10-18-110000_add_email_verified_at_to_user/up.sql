@@ -0,0 +1,4 @@
+ALTER TABLE "user" ADD COLUMN email_verified_at TIMESTAMP;
+
+-- Accounts created before verification existed keep working.
+UPDATE "user" SET email_verified_at = created_at;
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE audit_event;
+DROP TABLE login_attempt;
+ALTER TABLE "user" DROP COLUMN account_locked_until;
\ No newline at end of file
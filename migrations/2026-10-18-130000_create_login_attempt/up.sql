@@ -0,0 +1,26 @@
+ALTER TABLE "user" ADD COLUMN account_locked_until TIMESTAMP;
+
+CREATE TABLE login_attempt (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID,
+    email TEXT NOT NULL,
+    ip_address TEXT NOT NULL,
+    succeeded BOOLEAN NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    CONSTRAINT fk_user_id FOREIGN KEY (user_id) REFERENCES "user" (id) ON DELETE CASCADE
+);
+
+CREATE INDEX login_attempt_user_id_created_at_idx ON login_attempt (user_id, created_at);
+CREATE INDEX login_attempt_ip_address_created_at_idx ON login_attempt (ip_address, created_at);
+
+CREATE TABLE audit_event (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID,
+    event TEXT NOT NULL,
+    ip_address TEXT,
+    details JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    CONSTRAINT fk_user_id FOREIGN KEY (user_id) REFERENCES "user" (id) ON DELETE SET NULL
+);
+
+CREATE INDEX audit_event_user_id_idx ON audit_event (user_id);
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE recovery_code;
+ALTER TABLE "user" DROP COLUMN totp_enabled_at;
+ALTER TABLE "user" DROP COLUMN totp_secret;
\ No newline at end of file
@@ -0,0 +1,13 @@
+ALTER TABLE "user" ADD COLUMN totp_secret TEXT;
+ALTER TABLE "user" ADD COLUMN totp_enabled_at TIMESTAMP;
+
+CREATE TABLE recovery_code (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL,
+    code_hash TEXT NOT NULL,
+    used_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    CONSTRAINT fk_user_id FOREIGN KEY (user_id) REFERENCES "user" (id) ON DELETE CASCADE
+);
+
+CREATE INDEX recovery_code_user_id_idx ON recovery_code (user_id);
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE oidc_auth_request;
+DROP TABLE identity;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE "user" ADD COLUMN role TEXT NOT NULL DEFAULT 'user' CHECK (role IN ('user', 'admin'));
\ No newline at end of file
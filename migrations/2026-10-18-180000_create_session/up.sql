@@ -0,0 +1,24 @@
+-- The id of a session is the id of the refresh token family it was started with.
+CREATE TABLE session (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL,
+    user_agent TEXT,
+    ip_address TEXT,
+    access_jti UUID NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    last_seen_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    revoked_at TIMESTAMP,
+    CONSTRAINT fk_user_id FOREIGN KEY (user_id) REFERENCES "user" (id) ON DELETE CASCADE
+);
+
+CREATE INDEX session_user_id_idx ON session (user_id);
+
+-- Families that can still be refreshed become sessions, so nobody is logged out.
+INSERT INTO session (id, user_id, access_jti, created_at, last_seen_at)
+SELECT family_id, user_id, access_jti, created_at, created_at
+FROM refresh_token
+WHERE used_at IS NULL
+    AND revoked_at IS NULL
+    AND expires_at > current_timestamp
+    AND access_jti IS NOT NULL
+ON CONFLICT DO NOTHING;
\ No newline at end of file
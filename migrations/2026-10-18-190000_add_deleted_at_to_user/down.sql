@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "user" DROP COLUMN deleted_at;
+ALTER TABLE "user" DROP COLUMN deactivated_at;
\ No newline at end of file
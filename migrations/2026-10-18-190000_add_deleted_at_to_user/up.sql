@@ -0,0 +1,4 @@
+ALTER TABLE "user" ADD COLUMN deactivated_at TIMESTAMP;
+ALTER TABLE "user" ADD COLUMN deleted_at TIMESTAMP;
+
+CREATE INDEX user_deleted_at_idx ON "user" (deleted_at) WHERE deleted_at IS NOT NULL;
\ No newline at end of file
@@ -0,0 +1,14 @@
+CREATE TABLE data_export (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL,
+    format TEXT NOT NULL CHECK (format IN ('json', 'zip')),
+    status TEXT NOT NULL CHECK (status IN ('pending', 'completed', 'failed')),
+    content BYTEA,
+    error TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    completed_at TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL,
+    CONSTRAINT fk_user_id FOREIGN KEY (user_id) REFERENCES "user" (id) ON DELETE CASCADE
+);
+
+CREATE INDEX data_export_user_id_idx ON data_export (user_id);
\ No newline at end of file
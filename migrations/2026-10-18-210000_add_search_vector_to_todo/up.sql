@@ -0,0 +1,6 @@
+ALTER TABLE todo ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', title), 'A') ||
+    setweight(to_tsvector('english', description), 'B')
+) STORED;
+
+CREATE INDEX todo_search_vector_idx ON todo USING GIN (search_vector);
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "user" DROP COLUMN timezone;
+
+ALTER TABLE todo DROP COLUMN remind_at, DROP COLUMN due_at;
\ No newline at end of file
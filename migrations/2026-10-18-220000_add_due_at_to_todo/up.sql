@@ -0,0 +1,8 @@
+ALTER TABLE todo
+    ADD COLUMN due_at TIMESTAMPTZ,
+    ADD COLUMN remind_at TIMESTAMPTZ,
+    ADD CONSTRAINT todo_remind_at_before_due_at CHECK (remind_at <= due_at);
+
+CREATE INDEX todo_user_id_due_at_idx ON todo (user_id, due_at) WHERE NOT done;
+
+ALTER TABLE "user" ADD COLUMN timezone TEXT NOT NULL DEFAULT 'UTC';
\ No newline at end of file
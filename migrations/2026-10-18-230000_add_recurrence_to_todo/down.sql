@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE todo DROP COLUMN occurrence, DROP COLUMN series_id, DROP COLUMN recurrence;
\ No newline at end of file
@@ -0,0 +1,7 @@
+ALTER TABLE todo
+    ADD COLUMN recurrence TEXT,
+    ADD COLUMN series_id UUID,
+    ADD COLUMN occurrence INTEGER NOT NULL DEFAULT 1,
+    ADD CONSTRAINT todo_recurrence_requires_due_at CHECK (recurrence IS NULL OR due_at IS NOT NULL);
+
+CREATE UNIQUE INDEX todo_series_id_occurrence_idx ON todo (series_id, occurrence);
\ No newline at end of file
@@ -0,0 +1,41 @@
+-- Priorities from low (0) to urgent (3), stored as numbers so that they sort.
+ALTER TABLE todo
+    ADD COLUMN priority SMALLINT NOT NULL DEFAULT 1 CHECK (priority BETWEEN 0 AND 3),
+    ADD COLUMN position TEXT COLLATE "C";
+
+-- The nth key in the order keys of new todos are handed out in: a0 to az,
+-- then b00 to bzz and so on.
+CREATE FUNCTION pg_temp.position_key(n BIGINT) RETURNS TEXT AS $$
+DECLARE
+    digits CONSTANT TEXT := '0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz';
+    head TEXT := 'a';
+    width INTEGER := 1;
+    span BIGINT := 62;
+    key TEXT := '';
+BEGIN
+    WHILE n >= span LOOP
+        n := n - span;
+        width := width + 1;
+        span := span * 62;
+        head := chr(ascii(head) + 1);
+    END LOOP;
+
+    FOR i IN 1..width LOOP
+        key := substr(digits, (n % 62)::INTEGER + 1, 1) || key;
+        n := n / 62;
+    END LOOP;
+
+    RETURN head || key;
+END
+$$ LANGUAGE plpgsql;
+
+UPDATE todo SET position = pg_temp.position_key(ordered.n - 1)
+FROM (
+    SELECT id, row_number() OVER (PARTITION BY user_id ORDER BY created_at, id) AS n
+    FROM todo
+) AS ordered
+WHERE todo.id = ordered.id;
+
+ALTER TABLE todo ALTER COLUMN position SET NOT NULL;
+
+CREATE UNIQUE INDEX todo_user_id_position_idx ON todo (user_id, position);
\ No newline at end of file
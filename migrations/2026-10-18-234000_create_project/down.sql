@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE todo DROP COLUMN project_id;
+
+DROP TABLE project;
\ No newline at end of file
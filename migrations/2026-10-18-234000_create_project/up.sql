@@ -0,0 +1,17 @@
+CREATE TABLE project (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL,
+    name TEXT NOT NULL,
+    color TEXT CHECK (color ~ '^#[0-9a-f]{6}$'),
+    archived BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP,
+    CONSTRAINT fk_user_id FOREIGN KEY (user_id) REFERENCES "user" (id) ON DELETE CASCADE
+);
+
+CREATE INDEX project_user_id_idx ON project (user_id);
+
+-- Todos of a deleted project go back to the list of todos without one.
+ALTER TABLE todo ADD COLUMN project_id UUID REFERENCES project (id) ON DELETE SET NULL;
+
+CREATE INDEX todo_project_id_idx ON todo (project_id);
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE todo_tag;
+
+DROP TABLE tag;
\ No newline at end of file
@@ -0,0 +1,19 @@
+CREATE TABLE tag (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL,
+    name TEXT NOT NULL,
+    color TEXT CHECK (color ~ '^#[0-9a-f]{6}$'),
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP,
+    CONSTRAINT fk_user_id FOREIGN KEY (user_id) REFERENCES "user" (id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX tag_user_id_name_idx ON tag (user_id, name);
+
+CREATE TABLE todo_tag (
+    todo_id UUID NOT NULL REFERENCES todo (id) ON DELETE CASCADE,
+    tag_id UUID NOT NULL REFERENCES tag (id) ON DELETE CASCADE,
+    PRIMARY KEY (todo_id, tag_id)
+);
+
+CREATE INDEX todo_tag_tag_id_idx ON todo_tag (tag_id);
\ No newline at end of file
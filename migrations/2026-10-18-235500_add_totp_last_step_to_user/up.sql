@@ -0,0 +1,2 @@
+-- Time step of the last TOTP code accepted, so that it can not be replayed.
+ALTER TABLE "user" ADD COLUMN totp_last_step BIGINT;
\ No newline at end of file
@@ -1,4 +1,7 @@
-use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use actix_web::{
+    http::{header::RETRY_AFTER, StatusCode},
+    HttpResponse, ResponseError,
+};
 
 use diesel::result::Error as DieselError;
 use log::error;
@@ -10,6 +13,8 @@ use std::fmt;
 pub struct ApiError {
     pub status_code: u16,
     pub message: String,
+    #[serde(skip)]
+    pub retry_after: Option<u64>,
 }
 
 impl ApiError {
@@ -17,6 +22,7 @@ impl ApiError {
         ApiError {
             status_code,
             message,
+            retry_after: None,
         }
     }
 
@@ -32,9 +38,21 @@ impl ApiError {
         ApiError::new(400, message)
     }
 
+    pub fn forbidden(message: String) -> ApiError {
+        ApiError::new(403, message)
+    }
+
     pub fn not_found(message: String) -> ApiError {
         ApiError::new(404, message)
     }
+
+    /// Builds a 429 telling the client to wait `retry_after` seconds.
+    pub fn too_many_requests(message: String, retry_after: u64) -> ApiError {
+        ApiError {
+            retry_after: Some(retry_after),
+            ..ApiError::new(429, message)
+        }
+    }
 }
 
 impl fmt::Display for ApiError {
@@ -68,6 +86,12 @@ impl ResponseError for ApiError {
             }
         };
 
-        HttpResponse::build(status_code).json(json!({ "message": message }))
+        let mut response = HttpResponse::build(status_code);
+
+        if let Some(retry_after) = self.retry_after {
+            response.insert_header((RETRY_AFTER, retry_after.to_string()));
+        }
+
+        response.json(json!({ "message": message }))
     }
 }
@@ -0,0 +1,55 @@
+use crate::{api_error::ApiError, db, schema::*};
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+/// A security relevant event, kept so that account activity can be reviewed later.
+#[derive(Serialize, Deserialize, Queryable, Insertable, Debug)]
+#[table_name = "audit_event"]
+pub struct AuditEvent {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub event: String,
+    pub ip_address: Option<String>,
+    pub details: Value,
+    pub created_at: NaiveDateTime,
+}
+
+impl AuditEvent {
+    pub fn record(
+        user_id: Option<Uuid>,
+        event: &str,
+        ip_address: Option<&str>,
+        details: Value,
+    ) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let audit_event = AuditEvent {
+            id: Uuid::new_v4(),
+            user_id,
+            event: event.to_string(),
+            ip_address: ip_address.map(str::to_string),
+            details,
+            created_at: Utc::now().naive_utc(),
+        };
+
+        let audit_event = diesel::insert_into(audit_event::table)
+            .values(audit_event)
+            .get_result(&mut conn)?;
+
+        Ok(audit_event)
+    }
+
+    pub fn find_all(user_id: Uuid) -> Result<Vec<Self>, ApiError> {
+        let mut conn = db::connection()?;
+
+        let audit_events = audit_event::table
+            .filter(audit_event::user_id.eq(user_id))
+            .order(audit_event::created_at.desc())
+            .load(&mut conn)?;
+
+        Ok(audit_events)
+    }
+}
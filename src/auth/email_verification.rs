@@ -0,0 +1,91 @@
+use chrono::{Duration, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    api_error::ApiError,
+    mailer::{self, app_url, Email},
+    settings::setting,
+    user::User,
+};
+
+use super::{keys::key_ring, revoked_token::RevokedToken, service::expiry};
+
+pub const TOKEN_TYPE: &str = "verify-email+jwt";
+
+/// Claims of the signed link sent to a user to confirm their address.
+///
+/// The address is part of the token so that a link stops working once the
+/// user changes their email.
+#[derive(Debug, Serialize, Deserialize)]
+struct VerificationClaims {
+    sub: Uuid,
+    email: String,
+    exp: i64,
+    iat: i64,
+    iss: String,
+    aud: String,
+    jti: Uuid,
+}
+
+/// Emails `user` a single-use link to verify their address.
+pub fn send_verification(user: &User) -> Result<(), ApiError> {
+    let now = Utc::now();
+    let key_ring = key_ring();
+
+    let claims = VerificationClaims {
+        sub: user.id,
+        email: user.email.clone(),
+        exp: (now + ttl()).timestamp(),
+        iat: now.timestamp(),
+        iss: key_ring.issuer.clone(),
+        aud: key_ring.audience.clone(),
+        jti: Uuid::new_v4(),
+    };
+
+    let token = key_ring.encode(&claims, TOKEN_TYPE).map_err(|e| {
+        error!("{}", e);
+        ApiError::internal_server_error()
+    })?;
+
+    mailer::send(Email {
+        to: user.email.clone(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Hi {},\n\nPlease verify your email address by opening the link below:\n\n{}\n",
+            user.name,
+            app_url(&format!("/auth/verify-email?token={}", token))
+        ),
+    })
+}
+
+/// Marks the address in `token` as verified and burns the token.
+pub fn verify_email(token: &str) -> Result<User, ApiError> {
+    let invalid = || ApiError::bad_request("Invalid or expired verification token".to_string());
+
+    let claims: VerificationClaims = key_ring().decode(token, TOKEN_TYPE).map_err(|e| {
+        error!("{}", e);
+        invalid()
+    })?;
+
+    if RevokedToken::is_revoked(claims.jti)? {
+        return Err(invalid());
+    }
+
+    let user = User::find(claims.sub).map_err(|_| invalid())?;
+
+    if user.email != claims.email {
+        return Err(invalid());
+    }
+
+    RevokedToken::revoke(claims.jti, user.id, expiry(claims.exp))?;
+
+    User::mark_email_verified(user.id)
+}
+
+fn ttl() -> Duration {
+    let seconds = setting("EMAIL_VERIFICATION_EXPIRY_SECONDS", 60 * 60 * 24);
+
+    Duration::seconds(seconds)
+}
@@ -0,0 +1,61 @@
+use std::marker::PhantomData;
+
+use actix_web::{dev::Payload, FromRequest, HttpRequest};
+use futures::future::LocalBoxFuture;
+
+use crate::{
+    api_error::ApiError,
+    user::{Role, User},
+};
+
+use super::Claims;
+
+/// A role handlers can require through [`RequireRole`].
+pub trait RequiredRole {
+    const ROLE: Role;
+}
+
+pub struct Admin;
+
+impl RequiredRole for Admin {
+    const ROLE: Role = Role::Admin;
+}
+
+/// Extractor that rejects requests whose access token does not carry the role `R`.
+///
+/// Roles are read from the token, so a changed role takes effect once the
+/// user's token is refreshed. The user is loaded like the [`User`] extractor
+/// does, so revoked sessions and inactive accounts are turned away right away.
+pub struct RequireRole<R: RequiredRole> {
+    pub claims: Claims,
+    pub user: User,
+    role: PhantomData<R>,
+}
+
+impl<R: RequiredRole> FromRequest for RequireRole<R> {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let claims = Claims::from_request(req, payload);
+        let user = User::from_request(req, payload);
+
+        Box::pin(async move {
+            let claims = claims.await?;
+            let user = user.await?;
+
+            if !claims.has_role(R::ROLE) {
+                return Err(ApiError::forbidden(format!(
+                    "This action requires the {} role",
+                    R::ROLE
+                )));
+            }
+
+            Ok(RequireRole {
+                claims,
+                user,
+                role: PhantomData,
+            })
+        })
+    }
+}
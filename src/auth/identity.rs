@@ -0,0 +1,72 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{api_error::ApiError, db, schema::*};
+
+/// Links an account at an external identity provider to a user.
+#[derive(Queryable, Insertable, Debug)]
+#[table_name = "identity"]
+pub struct Identity {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_login_at: Option<NaiveDateTime>,
+}
+
+impl Identity {
+    pub fn find(provider: &str, subject: &str) -> Result<Option<Self>, ApiError> {
+        let mut conn = db::connection()?;
+
+        let identity = identity::table
+            .filter(identity::provider.eq(provider))
+            .filter(identity::subject.eq(subject))
+            .first(&mut conn)
+            .optional()?;
+
+        Ok(identity)
+    }
+
+    pub fn link(
+        user_id: Uuid,
+        provider: &str,
+        subject: &str,
+        email: Option<&str>,
+    ) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+        let now = Utc::now().naive_utc();
+
+        let identity = Identity {
+            id: Uuid::new_v4(),
+            user_id,
+            provider: provider.to_string(),
+            subject: subject.to_string(),
+            email: email.map(str::to_string),
+            created_at: now,
+            last_login_at: Some(now),
+        };
+
+        let identity = diesel::insert_into(identity::table)
+            .values(identity)
+            .get_result(&mut conn)?;
+
+        Ok(identity)
+    }
+
+    pub fn touch(id: Uuid, email: Option<&str>) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let identity = diesel::update(identity::table)
+            .filter(identity::id.eq(id))
+            .set((
+                identity::email.eq(email),
+                identity::last_login_at.eq(Utc::now().naive_utc()),
+            ))
+            .get_result(&mut conn)?;
+
+        Ok(identity)
+    }
+}
@@ -0,0 +1,269 @@
+use std::{env, error::Error, fs};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    decode, decode_header, encode,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use lazy_static::lazy_static;
+use log::info;
+use serde::{de::DeserializeOwned, Serialize};
+use simple_asn1::{from_der, ASN1Block};
+
+lazy_static! {
+    static ref KEY_RING: KeyRing = KeyRing::from_env();
+}
+
+pub fn init() {
+    info!("Loading JWT keys");
+    lazy_static::initialize(&KEY_RING);
+}
+
+pub fn key_ring() -> &'static KeyRing {
+    &KEY_RING
+}
+
+pub struct Key {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    encoding: Option<EncodingKey>,
+    decoding: DecodingKey,
+    jwk: Option<Jwk>,
+}
+
+/// Every key tokens may be verified with, plus the one new tokens are signed with.
+///
+/// Several keys can be active at once so that a new key can be introduced
+/// while tokens signed by the previous one are still in circulation.
+pub struct KeyRing {
+    pub keys: Vec<Key>,
+    pub signing_kid: String,
+    pub issuer: String,
+    pub audience: String,
+}
+
+impl KeyRing {
+    /// Reads keys from `JWT_KEYS`, a comma-separated list of `kid:ALG:source` entries.
+    ///
+    /// For `HS256` the source names the env var holding the secret. For `RS256`
+    /// and `EdDSA` it is `public.pem` or `public.pem:private.pem`; keys without
+    /// a private half can only verify tokens.
+    fn from_env() -> Self {
+        let specs = env::var("JWT_KEYS").unwrap_or_else(|_| "default:HS256:JWT_SECRET".to_string());
+
+        let keys: Vec<Key> = specs
+            .split(',')
+            .map(str::trim)
+            .filter(|spec| !spec.is_empty())
+            .map(|spec| {
+                Key::parse(spec).unwrap_or_else(|e| panic!("Invalid JWT key {}: {}", spec, e))
+            })
+            .collect();
+
+        let signing_kid = env::var("JWT_SIGNING_KID")
+            .ok()
+            .or_else(|| keys.first().map(|key| key.kid.clone()))
+            .expect("JWT_KEYS does not contain any key");
+
+        let signing_key = keys
+            .iter()
+            .find(|key| key.kid == signing_kid)
+            .expect("JWT_SIGNING_KID does not match any key in JWT_KEYS");
+
+        if signing_key.encoding.is_none() {
+            panic!("JWT signing key {} has no private key", signing_kid);
+        }
+
+        KeyRing {
+            keys,
+            signing_kid,
+            issuer: env::var("JWT_ISSUER").unwrap_or_else(|_| "actix-todo-api".to_string()),
+            audience: env::var("JWT_AUDIENCE").unwrap_or_else(|_| "actix-todo-api".to_string()),
+        }
+    }
+
+    /// Signs `claims` with the signing key. `token_type` goes in the `typ`
+    /// header so tokens issued for one purpose are not accepted for another.
+    pub fn encode<T: Serialize>(
+        &self,
+        claims: &T,
+        token_type: &str,
+    ) -> Result<String, Box<dyn Error>> {
+        let key = self
+            .find(&self.signing_kid)
+            .ok_or("Signing key not found")?;
+        let encoding = key
+            .encoding
+            .as_ref()
+            .ok_or("Signing key has no private key")?;
+
+        let mut header = Header::new(key.algorithm);
+        header.typ = Some(token_type.to_string());
+        header.kid = Some(key.kid.clone());
+
+        Ok(encode(&header, claims, encoding)?)
+    }
+
+    /// Verifies the type, signature, expiry, issuer and audience of `token`.
+    pub fn decode<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        token_type: &str,
+    ) -> Result<T, Box<dyn Error>> {
+        let header = decode_header(token)?;
+
+        if header.typ.as_deref() != Some(token_type) {
+            return Err("Unexpected token type".into());
+        }
+
+        let kid = header.kid.ok_or("Token has no kid")?;
+        let key = self
+            .find(&kid)
+            .ok_or("Token was signed with an unknown key")?;
+
+        if header.alg != key.algorithm {
+            return Err("Token algorithm does not match its key".into());
+        }
+
+        let mut validation = Validation::new(key.algorithm);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        Ok(decode::<T>(token, &key.decoding, &validation)?.claims)
+    }
+
+    /// Public halves of the asymmetric keys, for verifiers that do not share our secrets.
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.keys.iter().filter_map(|key| key.jwk.clone()).collect(),
+        }
+    }
+
+    fn find(&self, kid: &str) -> Option<&Key> {
+        self.keys.iter().find(|key| key.kid == kid)
+    }
+}
+
+impl Key {
+    fn parse(spec: &str) -> Result<Self, Box<dyn Error>> {
+        let mut parts = spec.splitn(3, ':');
+
+        let kid = parts
+            .next()
+            .filter(|kid| !kid.is_empty())
+            .ok_or("Missing kid")?;
+        let algorithm = parts.next().ok_or("Missing algorithm")?;
+        let source = parts.next().ok_or("Missing key source")?;
+
+        let (algorithm, encoding, decoding, jwk) = match algorithm {
+            "HS256" => {
+                let secret = env::var(source).map_err(|_| format!("{} not set", source))?;
+
+                (
+                    Algorithm::HS256,
+                    Some(EncodingKey::from_secret(secret.as_bytes())),
+                    DecodingKey::from_secret(secret.as_bytes()),
+                    None,
+                )
+            }
+            "RS256" | "EdDSA" => {
+                let (public_path, private_path) = match source.split_once(':') {
+                    Some((public_path, private_path)) => (public_path, Some(private_path)),
+                    None => (source, None),
+                };
+
+                let public_pem = fs::read(public_path)?;
+                let private_pem = private_path.map(fs::read).transpose()?;
+                let public_key = subject_public_key(&public_pem)?;
+
+                if algorithm == "RS256" {
+                    (
+                        Algorithm::RS256,
+                        private_pem
+                            .map(|pem| EncodingKey::from_rsa_pem(&pem))
+                            .transpose()?,
+                        DecodingKey::from_rsa_pem(&public_pem)?,
+                        Some(rsa_jwk(kid, &public_key)?),
+                    )
+                } else {
+                    (
+                        Algorithm::EdDSA,
+                        private_pem
+                            .map(|pem| EncodingKey::from_ed_pem(&pem))
+                            .transpose()?,
+                        DecodingKey::from_ed_pem(&public_pem)?,
+                        Some(ed25519_jwk(kid, &public_key)),
+                    )
+                }
+            }
+            algorithm => return Err(format!("Unsupported algorithm {}", algorithm).into()),
+        };
+
+        Ok(Key {
+            kid: kid.to_string(),
+            algorithm,
+            encoding,
+            decoding,
+            jwk,
+        })
+    }
+}
+
+/// Extracts the key bytes from a PEM encoded `SubjectPublicKeyInfo`.
+fn subject_public_key(public_pem: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let der = pem::parse(public_pem)?;
+
+    match from_der(der.contents())?.first() {
+        Some(ASN1Block::Sequence(_, fields)) => match fields.get(1) {
+            Some(ASN1Block::BitString(_, _, key)) => Ok(key.clone()),
+            _ => Err("Public key is missing its key bits".into()),
+        },
+        _ => Err("Public key is not a SubjectPublicKeyInfo".into()),
+    }
+}
+
+fn rsa_jwk(kid: &str, public_key: &[u8]) -> Result<Jwk, Box<dyn Error>> {
+    let (n, e) = match from_der(public_key)?.first() {
+        Some(ASN1Block::Sequence(_, fields)) => match (fields.first(), fields.get(1)) {
+            (Some(ASN1Block::Integer(_, n)), Some(ASN1Block::Integer(_, e))) => {
+                (n.to_bytes_be().1, e.to_bytes_be().1)
+            }
+            _ => return Err("RSA public key is missing its modulus or exponent".into()),
+        },
+        _ => return Err("Invalid RSA public key".into()),
+    };
+
+    Ok(Jwk {
+        common: jwk_common(kid, KeyAlgorithm::RS256),
+        algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+            key_type: RSAKeyType::RSA,
+            n: URL_SAFE_NO_PAD.encode(n),
+            e: URL_SAFE_NO_PAD.encode(e),
+        }),
+    })
+}
+
+fn ed25519_jwk(kid: &str, public_key: &[u8]) -> Jwk {
+    Jwk {
+        common: jwk_common(kid, KeyAlgorithm::EdDSA),
+        algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x: URL_SAFE_NO_PAD.encode(public_key),
+        }),
+    }
+}
+
+fn jwk_common(kid: &str, algorithm: KeyAlgorithm) -> CommonParameters {
+    CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_algorithm: Some(algorithm),
+        key_id: Some(kid.to_string()),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,180 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{
+    dsl::{count_star, max},
+    prelude::*,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{api_error::ApiError, audit::AuditEvent, db, schema::*, settings::setting, user::User};
+
+#[derive(Queryable, Insertable, Debug)]
+#[table_name = "login_attempt"]
+pub struct LoginAttempt {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub email: String,
+    pub ip_address: String,
+    pub succeeded: bool,
+    pub created_at: NaiveDateTime,
+}
+
+impl LoginAttempt {
+    /// Rejects logins from `ip_address` with a 429 while it is backing off.
+    pub fn check_ip(ip_address: &str) -> Result<(), ApiError> {
+        match Self::ip_retry_after(ip_address)? {
+            Some(retry_after) => Err(ApiError::too_many_requests(
+                "Too many failed login attempts, try again later".to_string(),
+                retry_after,
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Rejects logins to `user` with a 429 while the account is locked.
+    pub fn check_account(user: &User) -> Result<(), ApiError> {
+        let retry_after = match user.account_locked_until {
+            Some(locked_until) => (locked_until - Utc::now().naive_utc()).num_seconds(),
+            None => return Ok(()),
+        };
+
+        if retry_after <= 0 {
+            return Ok(());
+        }
+
+        Err(ApiError::too_many_requests(
+            "Account is temporarily locked after too many failed login attempts".to_string(),
+            retry_after as u64,
+        ))
+    }
+
+    /// Seconds `ip_address` has to wait before trying again, if it failed too
+    /// often recently. The wait doubles with every failure over the limit.
+    fn ip_retry_after(ip_address: &str) -> Result<Option<u64>, ApiError> {
+        let mut conn = db::connection()?;
+        let now = Utc::now().naive_utc();
+
+        let (failures, last_failure) = login_attempt::table
+            .filter(login_attempt::ip_address.eq(ip_address))
+            .filter(login_attempt::succeeded.eq(false))
+            .filter(login_attempt::created_at.gt(now - ip_window()))
+            .select((count_star(), max(login_attempt::created_at)))
+            .first::<(i64, Option<NaiveDateTime>)>(&mut conn)?;
+
+        let excess = failures - setting("LOGIN_IP_MAX_FAILED_ATTEMPTS", 20);
+
+        let retry_after = match last_failure {
+            Some(last_failure) if excess >= 0 => {
+                (last_failure + backoff(excess) - now).num_seconds()
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(u64::try_from(retry_after)
+            .ok()
+            .filter(|seconds| *seconds > 0))
+    }
+
+    /// Records a failed login and locks `user` once they failed too many times in a row.
+    pub fn record_failure(
+        user: Option<&User>,
+        email: &str,
+        ip_address: &str,
+    ) -> Result<(), ApiError> {
+        let mut conn = db::connection()?;
+
+        Self::insert(&mut conn, user, email, ip_address, false)?;
+
+        let user = match user {
+            Some(user) => user,
+            None => return Ok(()),
+        };
+
+        let now = Utc::now().naive_utc();
+
+        let last_success = login_attempt::table
+            .filter(login_attempt::user_id.eq(user.id))
+            .filter(login_attempt::succeeded.eq(true))
+            .select(max(login_attempt::created_at))
+            .first::<Option<NaiveDateTime>>(&mut conn)?
+            .unwrap_or(now - Duration::days(1));
+
+        let failures = login_attempt::table
+            .filter(login_attempt::user_id.eq(user.id))
+            .filter(login_attempt::succeeded.eq(false))
+            .filter(login_attempt::created_at.gt(last_success))
+            .filter(login_attempt::created_at.gt(now - Duration::days(1)))
+            .count()
+            .get_result::<i64>(&mut conn)?;
+
+        let excess = failures - setting("LOGIN_MAX_FAILED_ATTEMPTS", 5);
+
+        if excess >= 0 {
+            let locked_until = now + backoff(excess);
+
+            User::set_locked_until(user.id, Some(locked_until))?;
+
+            AuditEvent::record(
+                Some(user.id),
+                "account_locked",
+                Some(ip_address),
+                json!({ "failed_attempts": failures, "locked_until": locked_until }),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn record_success(user: &User, ip_address: &str) -> Result<(), ApiError> {
+        let mut conn = db::connection()?;
+
+        Self::insert(&mut conn, Some(user), &user.email, ip_address, true)?;
+
+        if user.account_locked_until.is_some() {
+            User::set_locked_until(user.id, None)?;
+        }
+
+        diesel::delete(login_attempt::table)
+            .filter(login_attempt::created_at.lt(Utc::now().naive_utc() - Duration::days(1)))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    fn insert(
+        conn: &mut PgConnection,
+        user: Option<&User>,
+        email: &str,
+        ip_address: &str,
+        succeeded: bool,
+    ) -> Result<(), ApiError> {
+        let login_attempt = LoginAttempt {
+            id: Uuid::new_v4(),
+            user_id: user.map(|user| user.id),
+            email: email.to_string(),
+            ip_address: ip_address.to_string(),
+            succeeded,
+            created_at: Utc::now().naive_utc(),
+        };
+
+        diesel::insert_into(login_attempt::table)
+            .values(login_attempt)
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
+
+/// Lockout duration after `excess` failures over the limit, doubling each time.
+fn backoff(excess: i64) -> Duration {
+    let base: i64 = setting("LOGIN_LOCKOUT_SECONDS", 60);
+    let max = setting("LOGIN_LOCKOUT_MAX_SECONDS", 60 * 60);
+
+    let seconds = base.saturating_mul(1 << excess.clamp(0, 32));
+
+    Duration::seconds(seconds.min(max))
+}
+
+fn ip_window() -> Duration {
+    Duration::seconds(setting("LOGIN_IP_WINDOW_SECONDS", 15 * 60))
+}
@@ -0,0 +1,208 @@
+use std::env;
+
+use chrono::{Duration, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+use crate::{api_error::ApiError, settings::setting, user::User};
+
+use super::{
+    keys::key_ring,
+    login_attempt::LoginAttempt,
+    models::{MfaPendingResponse, TotpEnrollment},
+    recovery_code::RecoveryCode,
+    revoked_token::RevokedToken,
+    service::expiry,
+};
+
+pub const TOKEN_TYPE: &str = "mfa+jwt";
+
+/// Claims of the token handed out after the password step of a login, for
+/// accounts that have two-factor authentication enabled.
+#[derive(Debug, Serialize, Deserialize)]
+struct MfaClaims {
+    sub: Uuid,
+    exp: i64,
+    iat: i64,
+    iss: String,
+    aud: String,
+    jti: Uuid,
+}
+
+/// Issues the short-lived token `user` exchanges at `/auth/login/mfa` together with a code.
+pub fn pending_response(user: &User) -> Result<MfaPendingResponse, ApiError> {
+    let now = Utc::now();
+    let key_ring = key_ring();
+    let ttl = ttl();
+
+    let claims = MfaClaims {
+        sub: user.id,
+        exp: (now + ttl).timestamp(),
+        iat: now.timestamp(),
+        iss: key_ring.issuer.clone(),
+        aud: key_ring.audience.clone(),
+        jti: Uuid::new_v4(),
+    };
+
+    let mfa_token = key_ring.encode(&claims, TOKEN_TYPE).map_err(|e| {
+        error!("{}", e);
+        ApiError::internal_server_error()
+    })?;
+
+    Ok(MfaPendingResponse {
+        mfa_required: true,
+        mfa_token,
+        expires_in: ttl.num_seconds(),
+    })
+}
+
+/// Checks `code` for the user `mfa_token` was issued to and burns the token once it succeeds.
+/// Locked and deactivated accounts are turned away before the code is looked at.
+///
+/// Returns the user and whether the code was valid, so failures can be counted
+/// against the account.
+pub fn complete_login(mfa_token: &str, code: &str) -> Result<(User, bool), ApiError> {
+    let invalid = || ApiError::unauthorized("Invalid or expired MFA token".to_string());
+
+    let claims: MfaClaims = key_ring().decode(mfa_token, TOKEN_TYPE).map_err(|e| {
+        error!("{}", e);
+        invalid()
+    })?;
+
+    if RevokedToken::is_revoked(claims.jti)? {
+        return Err(invalid());
+    }
+
+    let user = User::find(claims.sub).map_err(|_| invalid())?;
+
+    // Locked accounts must not get to burn a recovery code.
+    LoginAttempt::check_account(&user)?;
+    user.ensure_active()?;
+
+    if !verify_code(&user, code)? {
+        return Ok((user, false));
+    }
+
+    RevokedToken::revoke(claims.jti, user.id, expiry(claims.exp))?;
+
+    Ok((user, true))
+}
+
+/// Generates a new TOTP secret for `user`. It only takes effect once confirmed with a code.
+pub fn enroll(user: &User) -> Result<TotpEnrollment, ApiError> {
+    if user.totp_enabled_at.is_some() {
+        return Err(ApiError::new(
+            409,
+            "Two-factor authentication is already enabled".to_string(),
+        ));
+    }
+
+    let secret = Secret::generate_secret().to_encoded().to_string();
+    let totp = totp(user, &secret)?;
+
+    User::set_totp(user.id, Some(secret.clone()), None)?;
+
+    Ok(TotpEnrollment {
+        secret,
+        otpauth_uri: totp.get_url(),
+    })
+}
+
+/// Turns on two-factor authentication once `code` proves the authenticator is set
+/// up, and returns a fresh set of recovery codes.
+pub fn confirm(user: &User, code: &str) -> Result<Vec<String>, ApiError> {
+    let secret = match (&user.totp_secret, user.totp_enabled_at) {
+        (_, Some(_)) => {
+            return Err(ApiError::new(
+                409,
+                "Two-factor authentication is already enabled".to_string(),
+            ))
+        }
+        (Some(secret), None) => secret,
+        (None, None) => {
+            return Err(ApiError::bad_request(
+                "Two-factor authentication has not been set up".to_string(),
+            ))
+        }
+    };
+
+    if !check_totp(user, secret, code)? {
+        return Err(ApiError::bad_request("Invalid code".to_string()));
+    }
+
+    User::set_totp(user.id, Some(secret.clone()), Some(Utc::now().naive_utc()))?;
+
+    RecoveryCode::regenerate(user.id)
+}
+
+pub fn disable(user: &User) -> Result<(), ApiError> {
+    User::set_totp(user.id, None, None)?;
+    RecoveryCode::delete_all(user.id)?;
+
+    Ok(())
+}
+
+/// Accepts either a current TOTP code or one of the unused recovery codes of `user`.
+pub fn verify_code(user: &User, code: &str) -> Result<bool, ApiError> {
+    let secret = match (&user.totp_secret, user.totp_enabled_at) {
+        (Some(secret), Some(_)) => secret,
+        _ => return Ok(false),
+    };
+
+    if check_totp(user, secret, code)? {
+        return Ok(true);
+    }
+
+    RecoveryCode::consume(user.id, code)
+}
+
+/// Accepts codes of the current time step and the ones right before and after
+/// it, but each step only once, so that an observed code can not be replayed.
+fn check_totp(user: &User, secret: &str, code: &str) -> Result<bool, ApiError> {
+    let totp = totp(user, secret)?;
+    let current = Utc::now().timestamp() as u64 / totp.step;
+
+    let step = (current.saturating_sub(1)..=current + 1)
+        .find(|step| totp.check(code.trim(), step * totp.step));
+
+    match step {
+        Some(step) => User::use_totp_step(user.id, step as i64),
+        None => Ok(false),
+    }
+}
+
+fn totp(user: &User, secret: &str) -> Result<TOTP, ApiError> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|e| {
+            error!("{:?}", e);
+            ApiError::internal_server_error()
+        })?;
+
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        0,
+        30,
+        secret,
+        Some(issuer()),
+        user.email.clone(),
+    )
+    .map_err(|e| {
+        error!("{}", e);
+        ApiError::internal_server_error()
+    })
+}
+
+/// Name authenticator apps show next to the code.
+fn issuer() -> String {
+    env::var("TOTP_ISSUER").unwrap_or_else(|_| "Todo API".to_string())
+}
+
+fn ttl() -> Duration {
+    let seconds = setting("MFA_TOKEN_EXPIRY_SECONDS", 5 * 60);
+
+    Duration::seconds(seconds)
+}
@@ -1,12 +1,45 @@
+use std::rc::Rc;
+
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
     Error, HttpMessage,
 };
 use futures::future::{ok, LocalBoxFuture, Ready};
 
-use super::{service::verify, Claims};
+use crate::api_error::ApiError;
+
+use super::service::authorize;
+
+/// Rejects requests without a valid bearer token and makes their [`Claims`](super::Claims)
+/// available to handlers.
+///
+/// Routes registered with [`JwtVerifier::allow`] are let through untouched.
+/// Personal access tokens are refused unless enabled with
+/// [`JwtVerifier::personal_access_tokens`].
+#[derive(Clone, Default)]
+pub struct JwtVerifier {
+    public_routes: Rc<Vec<String>>,
+    personal_access_tokens: bool,
+}
+
+impl JwtVerifier {
+    pub fn new() -> Self {
+        JwtVerifier::default()
+    }
+
+    /// Marks `route` as public. Segments written as `{name}` match any value.
+    pub fn allow(mut self, route: &str) -> Self {
+        Rc::make_mut(&mut self.public_routes).push(route.to_string());
+        self
+    }
 
-pub struct JwtVerifier;
+    /// Accepts personal access tokens as well. Handlers are responsible for
+    /// checking their scopes with [`Claims::require_scope`](super::Claims::require_scope).
+    pub fn personal_access_tokens(mut self) -> Self {
+        self.personal_access_tokens = true;
+        self
+    }
+}
 
 impl<S, B> Transform<S, ServiceRequest> for JwtVerifier
 where
@@ -21,12 +54,26 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ok(JwtVerifierMiddleware { service: std::rc::Rc::new(service) })
+        ok(JwtVerifierMiddleware {
+            service: Rc::new(service),
+            public_routes: self.public_routes.clone(),
+            personal_access_tokens: self.personal_access_tokens,
+        })
     }
 }
 
 pub struct JwtVerifierMiddleware<S> {
-    service: std::rc::Rc<S>,
+    service: Rc<S>,
+    public_routes: Rc<Vec<String>>,
+    personal_access_tokens: bool,
+}
+
+impl<S> JwtVerifierMiddleware<S> {
+    fn is_public(&self, path: &str) -> bool {
+        self.public_routes
+            .iter()
+            .any(|route| route_matches(route, path))
+    }
 }
 
 impl<S, B> Service<ServiceRequest> for JwtVerifierMiddleware<S>
@@ -42,17 +89,19 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-
-        let token = req.headers().get("Authorization");
-
-        if let Some(token) = token {
-            let token_str = token.to_str().unwrap();
-            let claims = verify(token_str).unwrap();
-            req.extensions_mut().insert::<Claims>(Claims {
-                id: claims.get("id").unwrap().to_string(),
-                name: claims.get("name").unwrap().to_string(),
-                email: claims.get("email").unwrap().to_string(),
-            });
+        if !self.is_public(req.path()) {
+            match authorize(req.request()) {
+                Ok(claims) if claims.scopes.is_some() && !self.personal_access_tokens => {
+                    let e = ApiError::forbidden(
+                        "Personal access tokens can not be used for this route".to_string(),
+                    );
+                    return Box::pin(async { Err(e.into()) });
+                }
+                Ok(claims) => {
+                    req.extensions_mut().insert(claims);
+                }
+                Err(e) => return Box::pin(async { Err(e.into()) }),
+            }
         }
 
         let service = self.service.clone();
@@ -63,3 +112,13 @@ where
         })
     }
 }
+
+fn route_matches(route: &str, path: &str) -> bool {
+    let route = route.trim_end_matches('/').split('/');
+    let path = path.trim_end_matches('/').split('/');
+
+    route.clone().count() == path.clone().count()
+        && route
+            .zip(path)
+            .all(|(expected, actual)| expected == actual || expected.starts_with('{'))
+}
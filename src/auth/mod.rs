@@ -1,9 +1,35 @@
+mod email_verification;
+mod guards;
+mod identity;
+mod keys;
+mod login_attempt;
+mod mfa;
 mod middlewares;
 pub mod models;
+mod oidc;
+pub mod password;
+mod password_reset;
+pub mod personal_access_token;
+mod recovery_code;
+mod refresh_token;
+mod revoked_token;
 mod routes;
 mod service;
+pub mod session;
 
+pub use guards::{Admin, RequireRole};
 pub use middlewares::JwtVerifier;
 pub use routes::init_routes;
-pub use service::Claims;
+pub use routes::init_well_known_routes;
+pub use service::check_session;
+pub use service::logout_everywhere;
 pub use service::verify;
+pub use service::Claims;
+
+/// Loads the signing keys, password hasher and identity providers so
+/// misconfiguration is caught at startup.
+pub fn init() {
+    keys::init();
+    password::init();
+    oidc::init();
+}
@@ -1,7 +1,9 @@
-use diesel::AsChangeset;
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 use validator::Validate;
-use crate::schema::*;
+
+use super::personal_access_token::{validate_scopes, PersonalAccessToken};
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
 pub struct LoginForm {
@@ -25,8 +27,7 @@ pub struct LoginForm {
     pub password: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, AsChangeset, Validate)]
-#[table_name = "user"]
+#[derive(Serialize, Deserialize, Validate)]
 pub struct RegisterForm {
     #[validate(required(message = "Name is required"))]
     pub name: Option<String>,
@@ -51,7 +52,165 @@ pub struct RegisterForm {
     pub password: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct VerifyEmailQuery {
+    #[validate(required(message = "Token is required"))]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ResendVerificationForm {
+    #[validate(
+        required(message = "Email is required"),
+        email(message = "Invalid email format")
+    )]
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ForgotPasswordForm {
+    #[validate(
+        required(message = "Email is required"),
+        email(message = "Invalid email format")
+    )]
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ResetPasswordForm {
+    #[validate(required(message = "Token is required"))]
+    pub token: Option<String>,
+
+    #[validate(
+        required(message = "Password is required"),
+        length(
+            min = 8,
+            message = "Password is too short. It must be at least 8 characters long"
+        )
+    )]
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ChangePasswordForm {
+    #[validate(required(message = "Current password is required"))]
+    pub current_password: Option<String>,
+
+    #[validate(
+        required(message = "New password is required"),
+        length(
+            min = 8,
+            message = "Password is too short. It must be at least 8 characters long"
+        )
+    )]
+    pub new_password: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ChangeEmailForm {
+    #[validate(
+        required(message = "Email is required"),
+        email(message = "Invalid email format")
+    )]
+    pub email: Option<String>,
+
+    #[validate(required(message = "Password is required"))]
+    pub password: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginResponse {
     pub token: String,
+    pub refresh_token: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionResponse {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+    /// Whether this is the session the request was made from.
+    pub current: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaPendingResponse {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct MfaLoginForm {
+    #[validate(required(message = "MFA token is required"))]
+    pub mfa_token: Option<String>,
+
+    #[validate(required(message = "Code is required"))]
+    pub code: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct TotpCodeForm {
+    #[validate(required(message = "Code is required"))]
+    pub code: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct DisableTotpForm {
+    #[validate(required(message = "Password is required"))]
+    pub password: Option<String>,
+
+    #[validate(required(message = "Code is required"))]
+    pub code: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreatePersonalAccessTokenForm {
+    #[validate(
+        required(message = "Name is required"),
+        length(
+            min = 1,
+            max = 100,
+            message = "Name must be between 1 and 100 characters long"
+        )
+    )]
+    pub name: Option<String>,
+
+    #[validate(required(message = "Scopes are required"), custom = "validate_scopes")]
+    pub scopes: Option<Vec<String>>,
+
+    #[validate(range(min = 1, max = 365, message = "Expiry must be between 1 and 365 days"))]
+    pub expires_in_days: Option<i64>,
+}
+
+/// Response to creating a personal access token, the only time its value is shown.
+#[derive(Debug, Serialize)]
+pub struct CreatedPersonalAccessToken {
+    pub token: String,
+    #[serde(flatten)]
+    pub personal_access_token: PersonalAccessToken,
+}
+
+/// Query the identity provider redirects back with, either `code` and `state` or an `error`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct RefreshForm {
+    #[validate(required(message = "Refresh token is required"))]
+    pub refresh_token: Option<String>,
 }
@@ -0,0 +1,168 @@
+use std::{env, error::Error};
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher as _, SaltString},
+    Algorithm, Argon2, Params, PasswordVerifier, Version,
+};
+use lazy_static::lazy_static;
+use log::info;
+
+lazy_static! {
+    static ref HASHER: PasswordHasher = PasswordHasher::from_env();
+    static ref DUMMY_HASH: String = HASHER
+        .hash("dummy password")
+        .unwrap_or_else(|e| panic!("Failed hashing dummy password: {}", e));
+}
+
+pub fn init() {
+    info!("Configuring password hasher");
+    lazy_static::initialize(&HASHER);
+    lazy_static::initialize(&DUMMY_HASH);
+}
+
+pub fn hasher() -> &'static PasswordHasher {
+    &HASHER
+}
+
+/// Checks `password` against a hash made with the current settings, so that a
+/// login without an account takes as long as one with a wrong password.
+pub fn verify_dummy(password: &str) {
+    let _ = HASHER.verify(password, &DUMMY_HASH);
+}
+
+/// Algorithm and parameters new password hashes are produced with.
+///
+/// Hashes are stored as self-describing strings (PHC for Argon2id, modular
+/// crypt for bcrypt), so hashes made with older settings keep verifying and
+/// can be upgraded on the next successful login.
+#[derive(Debug)]
+pub enum PasswordHasher {
+    Bcrypt {
+        cost: u32,
+    },
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
+
+impl PasswordHasher {
+    fn from_env() -> Self {
+        let var = |name: &str, default: u32| {
+            env::var(name)
+                .ok()
+                .map(|value| {
+                    value
+                        .parse()
+                        .unwrap_or_else(|_| panic!("{} must be a number", name))
+                })
+                .unwrap_or(default)
+        };
+
+        // Bad settings fail at startup rather than on every registration.
+        match env::var("PASSWORD_HASHER").as_deref() {
+            Ok("bcrypt") => {
+                let cost = var("BCRYPT_COST", bcrypt::DEFAULT_COST);
+                if !(4..=31).contains(&cost) {
+                    panic!("BCRYPT_COST must be between 4 and 31");
+                }
+
+                PasswordHasher::Bcrypt { cost }
+            }
+            Ok("argon2id") | Err(_) => {
+                let memory_kib = var("ARGON2_MEMORY_KIB", Params::DEFAULT_M_COST);
+                let iterations = var("ARGON2_ITERATIONS", Params::DEFAULT_T_COST);
+                let parallelism = var("ARGON2_PARALLELISM", Params::DEFAULT_P_COST);
+                if let Err(e) = Params::new(memory_kib, iterations, parallelism, None) {
+                    panic!("Invalid ARGON2_* settings: {}", e);
+                }
+
+                PasswordHasher::Argon2id {
+                    memory_kib,
+                    iterations,
+                    parallelism,
+                }
+            }
+            Ok(hasher) => panic!("Unsupported PASSWORD_HASHER {}", hasher),
+        }
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String, Box<dyn Error>> {
+        match self {
+            PasswordHasher::Bcrypt { cost } => Ok(bcrypt::hash(password, *cost)?),
+            PasswordHasher::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let params = Params::new(*memory_kib, *iterations, *parallelism, None)
+                    .map_err(|e| e.to_string())?;
+                let salt = SaltString::generate(&mut OsRng);
+
+                let hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password(password.as_bytes(), &salt)
+                    .map_err(|e| e.to_string())?;
+
+                Ok(hash.to_string())
+            }
+        }
+    }
+
+    /// Checks `password` against `hash`, whichever supported algorithm produced it.
+    pub fn verify(&self, password: &str, hash: &str) -> Result<bool, Box<dyn Error>> {
+        if hash.starts_with("$argon2") {
+            let hash = PasswordHash::new(hash).map_err(|e| e.to_string())?;
+
+            return Ok(Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok());
+        }
+
+        if hash.starts_with("$2") {
+            return Ok(bcrypt::verify(password, hash)?);
+        }
+
+        Ok(false)
+    }
+
+    /// Whether `hash` was produced with another algorithm or weaker parameters
+    /// than the ones currently configured.
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        match self {
+            PasswordHasher::Bcrypt { cost } => match bcrypt_cost(hash) {
+                Some(hash_cost) => hash_cost < *cost,
+                None => true,
+            },
+            PasswordHasher::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let hash = match PasswordHash::new(hash) {
+                    Ok(hash) if hash.algorithm == argon2::ARGON2ID_IDENT => hash,
+                    _ => return true,
+                };
+
+                match Params::try_from(&hash) {
+                    Ok(params) => {
+                        params.m_cost() < *memory_kib
+                            || params.t_cost() < *iterations
+                            || params.p_cost() < *parallelism
+                    }
+                    Err(_) => true,
+                }
+            }
+        }
+    }
+}
+
+/// Reads the cost out of a `$2b$<cost>$...` bcrypt hash.
+fn bcrypt_cost(hash: &str) -> Option<u32> {
+    let mut parts = hash.split('$');
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(""), Some(version), Some(cost)) if version.starts_with('2') => cost.parse().ok(),
+        _ => None,
+    }
+}
@@ -0,0 +1,117 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    api_error::ApiError,
+    db,
+    mailer::{self, Email},
+    schema::*,
+    settings::setting,
+    user::User,
+};
+
+use super::{
+    revoked_token::RevokedToken,
+    service::{generate_token, hash_token},
+};
+
+#[derive(Queryable, Insertable, Debug)]
+#[table_name = "password_reset"]
+pub struct PasswordReset {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl PasswordReset {
+    /// Emails `user` a one-time token to reset their password with.
+    ///
+    /// Any token requested earlier stops working.
+    pub fn request(user: &User) -> Result<(), ApiError> {
+        let mut conn = db::connection()?;
+
+        let token = generate_token();
+        let now = Utc::now().naive_utc();
+
+        let password_reset = PasswordReset {
+            id: Uuid::new_v4(),
+            user_id: user.id,
+            token_hash: hash_token(&token),
+            expires_at: now + ttl(),
+            used_at: None,
+            created_at: now,
+        };
+
+        conn.transaction::<_, ApiError, _>(|conn| {
+            diesel::delete(password_reset::table)
+                .filter(password_reset::user_id.eq(user.id))
+                .filter(password_reset::used_at.is_null())
+                .execute(conn)?;
+
+            diesel::insert_into(password_reset::table)
+                .values(password_reset)
+                .execute(conn)?;
+
+            Ok(())
+        })?;
+
+        mailer::send(Email {
+            to: user.email.clone(),
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Hi {},\n\nUse the token below to reset your password. It expires in {} minutes.\n\n{}\n\nIf you did not ask for a password reset you can ignore this email.\n",
+                user.name,
+                ttl().num_minutes(),
+                token
+            ),
+        })
+    }
+
+    /// Sets a new password for the owner of `token` and signs them out everywhere.
+    pub fn reset(token: &str, password: &str) -> Result<User, ApiError> {
+        let mut conn = db::connection()?;
+        let token_hash = hash_token(token);
+        let password = User::hash_password(password)?;
+
+        let user = conn.transaction::<_, ApiError, _>(|conn| {
+            let now = Utc::now().naive_utc();
+
+            let password_reset = password_reset::table
+                .filter(password_reset::token_hash.eq(&token_hash))
+                .filter(password_reset::used_at.is_null())
+                .filter(password_reset::expires_at.gt(now))
+                .for_update()
+                .first::<PasswordReset>(conn)
+                .optional()?
+                .ok_or_else(|| {
+                    ApiError::bad_request("Invalid or expired password reset token".to_string())
+                })?;
+
+            diesel::update(password_reset::table)
+                .filter(password_reset::id.eq(password_reset.id))
+                .set(password_reset::used_at.eq(now))
+                .execute(conn)?;
+
+            let user = diesel::update(user::table)
+                .filter(user::id.eq(password_reset.user_id))
+                .set((user::password.eq(password), user::updated_at.eq(now)))
+                .get_result::<User>(conn)?;
+
+            Ok(user)
+        })?;
+
+        RevokedToken::revoke_all(user.id)?;
+
+        Ok(user)
+    }
+}
+
+fn ttl() -> Duration {
+    let seconds = setting("PASSWORD_RESET_EXPIRY_SECONDS", 60 * 60);
+
+    Duration::seconds(seconds)
+}
@@ -0,0 +1,163 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use rand::{distributions::Alphanumeric, Rng};
+use serde::Serialize;
+use uuid::Uuid;
+use validator::ValidationError;
+
+use crate::{api_error::ApiError, db, schema::*};
+
+use super::service::hash_token;
+
+/// Personal access tokens start with this so they can be told apart from JWTs,
+/// and spotted by secret scanners.
+pub const TOKEN_PREFIX: &str = "tdo_";
+
+pub const TODOS_READ: &str = "todos:read";
+pub const TODOS_WRITE: &str = "todos:write";
+
+pub const SCOPES: &[&str] = &[TODOS_READ, TODOS_WRITE];
+
+/// A long-lived token for scripts, limited to a set of scopes.
+#[derive(Serialize, Queryable, Insertable, Debug)]
+#[table_name = "personal_access_token"]
+pub struct PersonalAccessToken {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub user_id: Uuid,
+    pub name: String,
+    /// First characters of the token, shown so users can tell their tokens apart.
+    pub prefix: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl PersonalAccessToken {
+    pub fn find_all(user_id: Uuid) -> Result<Vec<Self>, ApiError> {
+        let mut conn = db::connection()?;
+
+        let tokens = personal_access_token::table
+            .filter(personal_access_token::user_id.eq(user_id))
+            .order(personal_access_token::created_at.desc())
+            .load(&mut conn)?;
+
+        Ok(tokens)
+    }
+
+    pub fn find(user_id: Uuid, id: Uuid) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let token = personal_access_token::table
+            .filter(personal_access_token::user_id.eq(user_id))
+            .filter(personal_access_token::id.eq(id))
+            .first(&mut conn)?;
+
+        Ok(token)
+    }
+
+    /// Creates a token and returns it along with its plaintext value, which is not stored.
+    pub fn create(
+        user_id: Uuid,
+        name: &str,
+        scopes: Vec<String>,
+        expires_in_days: Option<i64>,
+    ) -> Result<(Self, String), ApiError> {
+        let mut conn = db::connection()?;
+        let now = Utc::now().naive_utc();
+
+        let secret: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(40)
+            .map(char::from)
+            .collect();
+        let token = format!("{}{}", TOKEN_PREFIX, secret);
+
+        let personal_access_token = PersonalAccessToken {
+            id: Uuid::new_v4(),
+            user_id,
+            name: name.to_string(),
+            prefix: token[..TOKEN_PREFIX.len() + 8].to_string(),
+            token_hash: hash_token(&token),
+            scopes,
+            last_used_at: None,
+            expires_at: expires_in_days.map(|days| now + Duration::days(days)),
+            created_at: now,
+        };
+
+        let personal_access_token = diesel::insert_into(personal_access_token::table)
+            .values(personal_access_token)
+            .get_result(&mut conn)?;
+
+        Ok((personal_access_token, token))
+    }
+
+    pub fn delete(user_id: Uuid, id: Uuid) -> Result<usize, ApiError> {
+        let mut conn = db::connection()?;
+
+        let res = diesel::delete(personal_access_token::table)
+            .filter(personal_access_token::user_id.eq(user_id))
+            .filter(personal_access_token::id.eq(id))
+            .execute(&mut conn)?;
+
+        Ok(res)
+    }
+
+    /// Looks up the unexpired token `token` and records that it was used.
+    ///
+    /// Tokens of deactivated or deleted users are rejected.
+    pub fn authenticate(token: &str) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+        let now = Utc::now().naive_utc();
+
+        let personal_access_token: PersonalAccessToken = personal_access_token::table
+            .filter(personal_access_token::token_hash.eq(hash_token(token)))
+            .filter(
+                personal_access_token::expires_at
+                    .is_null()
+                    .or(personal_access_token::expires_at.gt(now)),
+            )
+            .inner_join(user::table)
+            .filter(user::deactivated_at.is_null())
+            .filter(user::deleted_at.is_null())
+            .select(personal_access_token::all_columns)
+            .first(&mut conn)
+            .optional()?
+            .ok_or_else(|| ApiError::unauthorized("Invalid personal access token".to_string()))?;
+
+        // Scripts may call the API in tight loops, a minute of precision is plenty.
+        let stale = personal_access_token
+            .last_used_at
+            .is_none_or(|last_used_at| last_used_at < now - Duration::minutes(1));
+
+        if stale {
+            diesel::update(personal_access_token::table)
+                .filter(personal_access_token::id.eq(personal_access_token.id))
+                .set(personal_access_token::last_used_at.eq(now))
+                .execute(&mut conn)?;
+        }
+
+        Ok(personal_access_token)
+    }
+}
+
+pub fn validate_scopes(scopes: &[String]) -> Result<(), ValidationError> {
+    let invalid = |message: &'static str| {
+        let mut error = ValidationError::new("scopes");
+        error.message = Some(message.into());
+        error
+    };
+
+    if scopes.is_empty() {
+        return Err(invalid("At least one scope is required"));
+    }
+
+    if scopes.iter().any(|scope| !SCOPES.contains(&scope.as_str())) {
+        return Err(invalid("Unknown scope, expected todos:read or todos:write"));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use rand::{distributions::Alphanumeric, Rng};
+use uuid::Uuid;
+
+use crate::{api_error::ApiError, db, schema::*};
+
+use super::service::hash_token;
+
+const CODE_COUNT: usize = 10;
+
+/// A single-use code that stands in for a TOTP code when the authenticator is lost.
+#[derive(Queryable, Insertable, Debug)]
+#[table_name = "recovery_code"]
+pub struct RecoveryCode {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub code_hash: String,
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl RecoveryCode {
+    /// Replaces the recovery codes of `user_id` with a fresh set and returns them.
+    ///
+    /// Only hashes are stored, so this is the only time the codes can be shown.
+    pub fn regenerate(user_id: Uuid) -> Result<Vec<String>, ApiError> {
+        let mut conn = db::connection()?;
+        let now = Utc::now().naive_utc();
+
+        let codes: Vec<String> = (0..CODE_COUNT).map(|_| generate_code()).collect();
+
+        let recovery_codes: Vec<RecoveryCode> = codes
+            .iter()
+            .map(|code| RecoveryCode {
+                id: Uuid::new_v4(),
+                user_id,
+                code_hash: hash_code(code),
+                used_at: None,
+                created_at: now,
+            })
+            .collect();
+
+        conn.transaction::<_, ApiError, _>(|conn| {
+            diesel::delete(recovery_code::table)
+                .filter(recovery_code::user_id.eq(user_id))
+                .execute(conn)?;
+
+            diesel::insert_into(recovery_code::table)
+                .values(recovery_codes)
+                .execute(conn)?;
+
+            Ok(())
+        })?;
+
+        Ok(codes)
+    }
+
+    /// Burns `code` if it is an unused recovery code of `user_id`.
+    pub fn consume(user_id: Uuid, code: &str) -> Result<bool, ApiError> {
+        let mut conn = db::connection()?;
+
+        let consumed = diesel::update(recovery_code::table)
+            .filter(recovery_code::user_id.eq(user_id))
+            .filter(recovery_code::code_hash.eq(hash_code(code)))
+            .filter(recovery_code::used_at.is_null())
+            .set(recovery_code::used_at.eq(Utc::now().naive_utc()))
+            .execute(&mut conn)?;
+
+        Ok(consumed > 0)
+    }
+
+    pub fn delete_all(user_id: Uuid) -> Result<usize, ApiError> {
+        let mut conn = db::connection()?;
+
+        let res = diesel::delete(recovery_code::table)
+            .filter(recovery_code::user_id.eq(user_id))
+            .execute(&mut conn)?;
+
+        Ok(res)
+    }
+}
+
+/// Generates a code like `k3f9x-2mq7d`.
+fn generate_code() -> String {
+    let code: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(10)
+        .map(|c| char::from(c).to_ascii_lowercase())
+        .collect();
+
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+/// Codes are hashed without the dash and case-insensitively, as users retype them by hand.
+fn hash_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+
+    hash_token(&normalized)
+}
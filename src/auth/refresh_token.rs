@@ -0,0 +1,176 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use log::warn;
+use uuid::Uuid;
+
+use crate::{api_error::ApiError, db, schema::*, settings::setting};
+
+use super::service::{generate_token, hash_token};
+
+#[derive(Queryable, Insertable, Debug)]
+#[table_name = "refresh_token"]
+pub struct RefreshToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub family_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub access_jti: Option<Uuid>,
+}
+
+/// Result of presenting a refresh token, decided inside the rotation transaction.
+enum Rotation {
+    Rotated {
+        user_id: Uuid,
+        family_id: Uuid,
+        token: String,
+    },
+    Reused,
+    Rejected,
+}
+
+impl RefreshToken {
+    /// Stores a new refresh token in `family_id` and returns its plaintext value.
+    /// Only the SHA-256 hash of the token is persisted, alongside the `jti` of
+    /// the access token issued with it so that it can be revoked later.
+    pub fn issue(user_id: Uuid, family_id: Uuid, access_jti: Uuid) -> Result<String, ApiError> {
+        let mut conn = db::connection()?;
+
+        Self::insert(&mut conn, user_id, family_id, access_jti)
+    }
+
+    /// Exchanges a refresh token for a new one in the same family, and returns
+    /// the owner and family along with it.
+    ///
+    /// Each token can only be used once. Presenting a token that was already
+    /// rotated means it leaked, so the whole family is revoked.
+    pub fn rotate(token: &str, access_jti: Uuid) -> Result<(Uuid, Uuid, String), ApiError> {
+        let mut conn = db::connection()?;
+        let token_hash = hash_token(token);
+
+        let rotation = conn.transaction::<_, ApiError, _>(|conn| {
+            let existing = refresh_token::table
+                .filter(refresh_token::token_hash.eq(&token_hash))
+                .for_update()
+                .first::<RefreshToken>(conn)
+                .optional()?;
+
+            let existing = match existing {
+                Some(existing) => existing,
+                None => return Ok(Rotation::Rejected),
+            };
+
+            let now = Utc::now().naive_utc();
+
+            if existing.used_at.is_some() {
+                diesel::update(refresh_token::table)
+                    .filter(refresh_token::family_id.eq(existing.family_id))
+                    .filter(refresh_token::revoked_at.is_null())
+                    .set(refresh_token::revoked_at.eq(now))
+                    .execute(conn)?;
+
+                diesel::update(session::table)
+                    .filter(session::id.eq(existing.family_id))
+                    .set(session::revoked_at.eq(now))
+                    .execute(conn)?;
+
+                return Ok(Rotation::Reused);
+            }
+
+            if existing.revoked_at.is_some() || existing.expires_at <= now {
+                return Ok(Rotation::Rejected);
+            }
+
+            diesel::update(refresh_token::table)
+                .filter(refresh_token::id.eq(existing.id))
+                .set(refresh_token::used_at.eq(now))
+                .execute(conn)?;
+
+            let token = Self::insert(conn, existing.user_id, existing.family_id, access_jti)?;
+
+            Ok(Rotation::Rotated {
+                user_id: existing.user_id,
+                family_id: existing.family_id,
+                token,
+            })
+        })?;
+
+        match rotation {
+            Rotation::Rotated {
+                user_id,
+                family_id,
+                token,
+            } => Ok((user_id, family_id, token)),
+            Rotation::Reused => {
+                warn!("Refresh token reuse detected, token family revoked");
+                Err(ApiError::unauthorized("Invalid refresh token".to_string()))
+            }
+            Rotation::Rejected => Err(ApiError::unauthorized("Invalid refresh token".to_string())),
+        }
+    }
+
+    /// Revokes the family the access token `access_jti` was issued with.
+    pub fn revoke_family_of(access_jti: Uuid) -> Result<usize, ApiError> {
+        let mut conn = db::connection()?;
+
+        let family_id = refresh_token::table
+            .filter(refresh_token::access_jti.eq(access_jti))
+            .select(refresh_token::family_id)
+            .first::<Uuid>(&mut conn)
+            .optional()?;
+
+        match family_id {
+            Some(family_id) => Self::revoke_family(family_id),
+            None => Ok(0),
+        }
+    }
+
+    pub fn revoke_family(family_id: Uuid) -> Result<usize, ApiError> {
+        let mut conn = db::connection()?;
+
+        let revoked = diesel::update(refresh_token::table)
+            .filter(refresh_token::family_id.eq(family_id))
+            .filter(refresh_token::revoked_at.is_null())
+            .set(refresh_token::revoked_at.eq(Utc::now().naive_utc()))
+            .execute(&mut conn)?;
+
+        Ok(revoked)
+    }
+
+    fn insert(
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        family_id: Uuid,
+        access_jti: Uuid,
+    ) -> Result<String, ApiError> {
+        let token = generate_token();
+        let now = Utc::now().naive_utc();
+
+        let refresh_token = RefreshToken {
+            id: Uuid::new_v4(),
+            user_id,
+            family_id,
+            token_hash: hash_token(&token),
+            expires_at: now + ttl(),
+            used_at: None,
+            revoked_at: None,
+            created_at: now,
+            access_jti: Some(access_jti),
+        };
+
+        diesel::insert_into(refresh_token::table)
+            .values(refresh_token)
+            .execute(conn)?;
+
+        Ok(token)
+    }
+}
+
+pub fn ttl() -> Duration {
+    let seconds = setting("REFRESH_TOKEN_EXPIRY_SECONDS", 60 * 60 * 24 * 30);
+
+    Duration::seconds(seconds)
+}
@@ -0,0 +1,112 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{api_error::ApiError, db, schema::*};
+
+use super::service::access_token_ttl;
+
+#[derive(Queryable, Insertable, Debug)]
+#[table_name = "revoked_token"]
+pub struct RevokedToken {
+    pub jti: Uuid,
+    pub user_id: Uuid,
+    pub expires_at: NaiveDateTime,
+    pub revoked_at: NaiveDateTime,
+}
+
+impl RevokedToken {
+    /// Adds `jti` to the revocation list until the token would have expired anyway.
+    pub fn revoke(jti: Uuid, user_id: Uuid, expires_at: NaiveDateTime) -> Result<(), ApiError> {
+        let mut conn = db::connection()?;
+
+        let revoked_token = RevokedToken {
+            jti,
+            user_id,
+            expires_at,
+            revoked_at: Utc::now().naive_utc(),
+        };
+
+        diesel::insert_into(revoked_token::table)
+            .values(revoked_token)
+            .on_conflict_do_nothing()
+            .execute(&mut conn)?;
+
+        Self::prune(&mut conn)?;
+
+        Ok(())
+    }
+
+    /// Revokes every session, access and refresh token of `user_id`.
+    ///
+    /// Access tokens are found through the refresh tokens they were issued
+    /// with; only those young enough to still be valid are added to the list.
+    pub fn revoke_all(user_id: Uuid) -> Result<(), ApiError> {
+        let mut conn = db::connection()?;
+
+        let now = Utc::now().naive_utc();
+        let ttl = access_token_ttl();
+
+        conn.transaction::<_, ApiError, _>(|conn| {
+            let live_jtis = refresh_token::table
+                .filter(refresh_token::user_id.eq(user_id))
+                .filter(refresh_token::created_at.gt(now - ttl))
+                .select(refresh_token::access_jti)
+                .load::<Option<Uuid>>(conn)?;
+
+            let revoked_tokens: Vec<RevokedToken> = live_jtis
+                .into_iter()
+                .flatten()
+                .map(|jti| RevokedToken {
+                    jti,
+                    user_id,
+                    expires_at: now + ttl,
+                    revoked_at: now,
+                })
+                .collect();
+
+            diesel::insert_into(revoked_token::table)
+                .values(revoked_tokens)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+
+            diesel::update(refresh_token::table)
+                .filter(refresh_token::user_id.eq(user_id))
+                .filter(refresh_token::revoked_at.is_null())
+                .set(refresh_token::revoked_at.eq(now))
+                .execute(conn)?;
+
+            diesel::update(session::table)
+                .filter(session::user_id.eq(user_id))
+                .filter(session::revoked_at.is_null())
+                .set(session::revoked_at.eq(now))
+                .execute(conn)?;
+
+            Ok(())
+        })?;
+
+        Self::prune(&mut conn)?;
+
+        Ok(())
+    }
+
+    pub fn is_revoked(jti: Uuid) -> Result<bool, ApiError> {
+        let mut conn = db::connection()?;
+
+        let revoked = diesel::select(diesel::dsl::exists(
+            revoked_token::table.filter(revoked_token::jti.eq(jti)),
+        ))
+        .get_result(&mut conn)?;
+
+        Ok(revoked)
+    }
+
+    /// Drops entries whose tokens have expired, as those are rejected regardless.
+    fn prune(conn: &mut PgConnection) -> Result<usize, ApiError> {
+        let pruned = diesel::delete(revoked_token::table)
+            .filter(revoked_token::expires_at.lt(Utc::now().naive_utc()))
+            .execute(conn)?;
+
+        Ok(pruned)
+    }
+}
@@ -1,20 +1,37 @@
-use std::collections::BTreeMap;
-
-use actix_web::{post, web, HttpResponse};
-
+use actix_web::{delete, get, http::header::LOCATION, post, put, web, HttpRequest, HttpResponse};
+use log::error;
+use serde_json::json;
+use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
     api_error::ApiError,
     auth::{
-        models::{LoginForm, LoginResponse, RegisterForm},
-        service::sign,
+        email_verification::{send_verification, verify_email as confirm_email},
+        keys::key_ring,
+        login_attempt::LoginAttempt,
+        mfa,
+        models::{
+            ChangeEmailForm, ChangePasswordForm, CreatePersonalAccessTokenForm,
+            CreatedPersonalAccessToken, DisableTotpForm, ForgotPasswordForm, LoginForm,
+            MfaLoginForm, OidcCallbackQuery, RefreshForm, RegisterForm, ResendVerificationForm,
+            ResetPasswordForm, SessionResponse, TotpCodeForm, VerifyEmailQuery,
+        },
+        oidc,
+        password::verify_dummy,
+        password_reset::PasswordReset,
+        personal_access_token::PersonalAccessToken,
+        recovery_code::RecoveryCode,
+        revoked_token::RevokedToken,
+        service::{client_ip, login_response, logout as revoke_session, refresh_response},
+        session::Session,
+        Claims,
     },
-    user::User,
+    user::{model::UserResponse, User},
 };
 
 #[post("/login")]
-async fn login(form: web::Json<LoginForm>) -> Result<HttpResponse, ApiError> {
+async fn login(req: HttpRequest, form: web::Json<LoginForm>) -> Result<HttpResponse, ApiError> {
     match form.validate() {
         Ok(_) => (),
         Err(e) => {
@@ -22,29 +39,172 @@ async fn login(form: web::Json<LoginForm>) -> Result<HttpResponse, ApiError> {
         }
     }
 
-    let user = User::find_by_email(form.email.as_ref().unwrap())?;
+    let ip_address = client_ip(&req);
+    let email = form.email.as_ref().unwrap();
+    let password = form.password.as_ref().unwrap();
+
+    LoginAttempt::check_ip(&ip_address)?;
+
+    let user = match User::find_by_email(email) {
+        Ok(user) => user,
+        Err(e) if e.status_code == 404 => {
+            verify_dummy(password);
+            LoginAttempt::record_failure(None, email, &ip_address)?;
+            return Err(ApiError::unauthorized("Invalid credentials".to_string()));
+        }
+        Err(e) => return Err(e),
+    };
+
+    let valid = user.verify_password(password)?;
+
+    LoginAttempt::check_account(&user)?;
 
-    if !user
-        .verify_password(form.password.as_ref().unwrap())
-        .unwrap()
-    {
+    if !valid {
+        LoginAttempt::record_failure(Some(&user), email, &ip_address)?;
         return Err(ApiError::unauthorized("Invalid credentials".to_string()));
     }
 
-    let user_id = user.id.to_string();
-    let name = user.name.to_string();
-    let email = user.email.to_string();
+    user.ensure_active()?;
 
-    let mut claims = BTreeMap::new();
-    claims.insert("id", user_id.as_str());
-    claims.insert("name", name.as_str());
-    claims.insert("email", email.as_str());
+    // With two-factor authentication the failure count is only reset once the code checks out too.
+    if user.totp_enabled_at.is_none() {
+        LoginAttempt::record_success(&user, &ip_address)?;
+    }
 
-    let token = sign(claims).map_err(|_| ApiError::internal_server_error());
+    if user.needs_password_rehash() {
+        if let Err(e) = User::update_password(user.id, password) {
+            error!("Failed upgrading password hash: {}", e);
+        }
+    }
 
-    Ok(HttpResponse::Ok().json(LoginResponse {
-        token: token.unwrap(),
-    }))
+    if user.email_verified_at.is_none() {
+        return Err(ApiError::forbidden(
+            "Email address has not been verified".to_string(),
+        ));
+    }
+
+    if user.totp_enabled_at.is_some() {
+        return Ok(HttpResponse::Ok().json(mfa::pending_response(&user)?));
+    }
+
+    Ok(HttpResponse::Ok().json(login_response(&user, &req)?))
+}
+
+#[post("/login/mfa")]
+async fn login_mfa(
+    req: HttpRequest,
+    form: web::Json<MfaLoginForm>,
+) -> Result<HttpResponse, ApiError> {
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    let ip_address = client_ip(&req);
+
+    LoginAttempt::check_ip(&ip_address)?;
+
+    let (user, valid) = mfa::complete_login(
+        form.mfa_token.as_ref().unwrap(),
+        form.code.as_ref().unwrap(),
+    )?;
+
+    if !valid {
+        LoginAttempt::record_failure(Some(&user), &user.email, &ip_address)?;
+        return Err(ApiError::unauthorized("Invalid code".to_string()));
+    }
+
+    LoginAttempt::record_success(&user, &ip_address)?;
+
+    Ok(HttpResponse::Ok().json(login_response(&user, &req)?))
+}
+
+/// Cancels the deletion of an account during its grace period. Deleted users
+/// cannot sign in, so they prove who they are with their password instead.
+#[post("/restore-account")]
+async fn restore_account(
+    req: HttpRequest,
+    form: web::Json<LoginForm>,
+) -> Result<HttpResponse, ApiError> {
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    let ip_address = client_ip(&req);
+    let email = form.email.as_ref().unwrap();
+
+    LoginAttempt::check_ip(&ip_address)?;
+
+    let password = form.password.as_ref().unwrap();
+
+    let user = match User::find_by_email(email) {
+        Ok(user) => user,
+        Err(e) if e.status_code == 404 => {
+            verify_dummy(password);
+            LoginAttempt::record_failure(None, email, &ip_address)?;
+            return Err(ApiError::unauthorized("Invalid credentials".to_string()));
+        }
+        Err(e) => return Err(e),
+    };
+
+    let valid = user.verify_password(password)?;
+
+    LoginAttempt::check_account(&user)?;
+
+    if !valid {
+        LoginAttempt::record_failure(Some(&user), email, &ip_address)?;
+        return Err(ApiError::unauthorized("Invalid credentials".to_string()));
+    }
+
+    if user.deleted_at.is_none() {
+        return Err(ApiError::new(
+            409,
+            "Account has not been deleted".to_string(),
+        ));
+    }
+
+    let user = User::restore(user.id).map_err(|e| match e.status_code {
+        404 => ApiError::new(410, "Account can no longer be restored".to_string()),
+        _ => e,
+    })?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Account restored",
+        "user": UserResponse::from(user),
+    })))
+}
+
+#[post("/refresh")]
+async fn refresh(form: web::Json<RefreshForm>) -> Result<HttpResponse, ApiError> {
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    let response = refresh_response(form.refresh_token.as_ref().unwrap())?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[post("/logout")]
+async fn logout(claims: Claims) -> Result<HttpResponse, ApiError> {
+    revoke_session(&claims)?;
+
+    Ok(HttpResponse::Ok().json(json!({ "message": "Logged out successfully" })))
+}
+
+#[post("/logout-all")]
+async fn logout_all(user: User) -> Result<HttpResponse, ApiError> {
+    RevokedToken::revoke_all(user.id)?;
+
+    Ok(HttpResponse::Ok().json(json!({ "message": "Logged out of all sessions" })))
 }
 
 #[post("/register")]
@@ -57,10 +217,376 @@ async fn register(user: web::Json<RegisterForm>) -> Result<HttpResponse, ApiErro
     };
 
     let user = User::create(user.into_inner())?;
-    Ok(HttpResponse::Ok().json(user))
+
+    if let Err(e) = send_verification(&user) {
+        error!("Failed sending verification email: {}", e);
+    }
+
+    Ok(HttpResponse::Ok().json(UserResponse::from(user)))
+}
+
+#[get("/verify-email")]
+async fn verify_email(query: web::Query<VerifyEmailQuery>) -> Result<HttpResponse, ApiError> {
+    match query.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    confirm_email(query.token.as_ref().unwrap())?;
+
+    Ok(HttpResponse::Ok().json(json!({ "message": "Email verified successfully" })))
+}
+
+#[post("/resend-verification")]
+async fn resend_verification(
+    form: web::Json<ResendVerificationForm>,
+) -> Result<HttpResponse, ApiError> {
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    // Respond the same way whether or not the account exists to avoid leaking which emails are registered.
+    if let Ok(user) = User::find_by_email(form.email.as_ref().unwrap()) {
+        if user.email_verified_at.is_none() {
+            if let Err(e) = send_verification(&user) {
+                error!("Failed sending verification email: {}", e);
+            }
+        }
+    }
+
+    Ok(HttpResponse::Accepted().json(json!({
+        "message": "If the account exists and is not verified yet, a verification email has been sent"
+    })))
+}
+
+#[get("/jwks.json")]
+async fn jwks() -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(("Cache-Control", "public, max-age=3600"))
+        .json(key_ring().jwks())
+}
+
+#[post("/forgot-password")]
+async fn forgot_password(form: web::Json<ForgotPasswordForm>) -> Result<HttpResponse, ApiError> {
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    // Always accept the request so the response does not reveal whether the account exists.
+    if let Ok(user) = User::find_by_email(form.email.as_ref().unwrap()) {
+        if let Err(e) = PasswordReset::request(&user) {
+            error!("Failed requesting password reset: {}", e);
+        }
+    }
+
+    Ok(HttpResponse::Accepted().json(json!({
+        "message": "If the account exists, a password reset email has been sent"
+    })))
+}
+
+#[post("/reset-password")]
+async fn reset_password(form: web::Json<ResetPasswordForm>) -> Result<HttpResponse, ApiError> {
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    PasswordReset::reset(
+        form.token.as_ref().unwrap(),
+        form.password.as_ref().unwrap(),
+    )?;
+
+    Ok(HttpResponse::Ok().json(json!({ "message": "Password reset successfully" })))
+}
+
+#[put("/password")]
+async fn change_password(
+    req: HttpRequest,
+    user: User,
+    form: web::Json<ChangePasswordForm>,
+) -> Result<HttpResponse, ApiError> {
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    if !user.verify_password(form.current_password.as_ref().unwrap())? {
+        return Err(ApiError::unauthorized("Invalid credentials".to_string()));
+    }
+
+    let user = User::update_password(user.id, form.new_password.as_ref().unwrap())?;
+
+    // Other sessions may belong to whoever knew the old password.
+    RevokedToken::revoke_all(user.id)?;
+
+    Ok(HttpResponse::Ok().json(login_response(&user, &req)?))
+}
+
+#[put("/email")]
+async fn change_email(
+    user: User,
+    form: web::Json<ChangeEmailForm>,
+) -> Result<HttpResponse, ApiError> {
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    if !user.verify_password(form.password.as_ref().unwrap())? {
+        return Err(ApiError::unauthorized("Invalid credentials".to_string()));
+    }
+
+    let user = User::update_email(user.id, form.email.as_ref().unwrap())?;
+
+    // The address is changed either way, a new link can be requested later.
+    if let Err(e) = send_verification(&user) {
+        error!("Failed sending verification email: {}", e);
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Email updated, check your inbox to verify the new address"
+    })))
+}
+
+#[post("/totp")]
+async fn enroll_totp(user: User) -> Result<HttpResponse, ApiError> {
+    let enrollment = mfa::enroll(&user)?;
+
+    Ok(HttpResponse::Ok().json(enrollment))
+}
+
+#[post("/totp/confirm")]
+async fn confirm_totp(user: User, form: web::Json<TotpCodeForm>) -> Result<HttpResponse, ApiError> {
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    let recovery_codes = mfa::confirm(&user, form.code.as_ref().unwrap())?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Two-factor authentication enabled",
+        "recovery_codes": recovery_codes
+    })))
+}
+
+#[post("/totp/recovery-codes")]
+async fn regenerate_recovery_codes(
+    user: User,
+    form: web::Json<TotpCodeForm>,
+) -> Result<HttpResponse, ApiError> {
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    if !mfa::verify_code(&user, form.code.as_ref().unwrap())? {
+        return Err(ApiError::unauthorized("Invalid code".to_string()));
+    }
+
+    let recovery_codes = RecoveryCode::regenerate(user.id)?;
+
+    Ok(HttpResponse::Ok().json(json!({ "recovery_codes": recovery_codes })))
+}
+
+#[delete("/totp")]
+async fn disable_totp(
+    user: User,
+    form: web::Json<DisableTotpForm>,
+) -> Result<HttpResponse, ApiError> {
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    if !user.verify_password(form.password.as_ref().unwrap())? {
+        return Err(ApiError::unauthorized("Invalid credentials".to_string()));
+    }
+
+    if !mfa::verify_code(&user, form.code.as_ref().unwrap())? {
+        return Err(ApiError::unauthorized("Invalid code".to_string()));
+    }
+
+    mfa::disable(&user)?;
+
+    Ok(HttpResponse::Ok().json(json!({ "message": "Two-factor authentication disabled" })))
+}
+
+#[get("/oidc/{provider}/start")]
+async fn oidc_start(provider: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let url = oidc::authorization_url(&provider).await?;
+
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, url))
+        .finish())
+}
+
+#[get("/oidc/{provider}/callback")]
+async fn oidc_callback(
+    req: HttpRequest,
+    provider: web::Path<String>,
+    query: web::Query<OidcCallbackQuery>,
+) -> Result<HttpResponse, ApiError> {
+    if let Some(error) = &query.error {
+        return Err(ApiError::bad_request(format!(
+            "Sign in was rejected by the identity provider: {}",
+            query.error_description.as_ref().unwrap_or(error)
+        )));
+    }
+
+    let (code, state) = match (&query.code, &query.state) {
+        (Some(code), Some(state)) => (code, state),
+        _ => {
+            return Err(ApiError::bad_request(
+                "Code and state are required".to_string(),
+            ))
+        }
+    };
+
+    let user = oidc::authenticate(&provider, code, state).await?;
+
+    LoginAttempt::check_account(&user)?;
+    user.ensure_active()?;
+
+    if user.totp_enabled_at.is_some() {
+        return Ok(HttpResponse::Ok().json(mfa::pending_response(&user)?));
+    }
+
+    LoginAttempt::record_success(&user, &client_ip(&req))?;
+
+    Ok(HttpResponse::Ok().json(login_response(&user, &req)?))
+}
+
+#[get("/tokens")]
+async fn list_personal_access_tokens(user: User) -> Result<HttpResponse, ApiError> {
+    let tokens = PersonalAccessToken::find_all(user.id)?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+#[post("/tokens")]
+async fn create_personal_access_token(
+    user: User,
+    form: web::Json<CreatePersonalAccessTokenForm>,
+) -> Result<HttpResponse, ApiError> {
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    let form = form.into_inner();
+
+    let (personal_access_token, token) = PersonalAccessToken::create(
+        user.id,
+        &form.name.unwrap(),
+        form.scopes.unwrap(),
+        form.expires_in_days,
+    )?;
+
+    Ok(HttpResponse::Created().json(CreatedPersonalAccessToken {
+        token,
+        personal_access_token,
+    }))
+}
+
+#[get("/tokens/{id}")]
+async fn find_personal_access_token(
+    user: User,
+    id: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let token = PersonalAccessToken::find(user.id, id.into_inner())?;
+
+    Ok(HttpResponse::Ok().json(token))
+}
+
+#[delete("/tokens/{id}")]
+async fn delete_personal_access_token(
+    user: User,
+    id: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    if PersonalAccessToken::delete(user.id, id.into_inner())? == 0 {
+        return Err(ApiError::not_found("Token not found".to_string()));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({ "message": "Token revoked successfully" })))
+}
+
+#[get("/sessions")]
+async fn sessions(claims: Claims, user: User) -> Result<HttpResponse, ApiError> {
+    let sessions: Vec<SessionResponse> = Session::find_active(user.id)?
+        .into_iter()
+        .map(|session| SessionResponse {
+            current: claims.sid == Some(session.id),
+            id: session.id,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+#[delete("/sessions/{id}")]
+async fn revoke_session_by_id(user: User, id: web::Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    if !Session::revoke(user.id, id.into_inner())? {
+        return Err(ApiError::not_found("Session not found".to_string()));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({ "message": "Session revoked successfully" })))
 }
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(login);
+    cfg.service(login_mfa);
+    cfg.service(restore_account);
+    cfg.service(refresh);
+    cfg.service(logout);
+    cfg.service(logout_all);
     cfg.service(register);
+    cfg.service(verify_email);
+    cfg.service(resend_verification);
+    cfg.service(forgot_password);
+    cfg.service(reset_password);
+    cfg.service(change_password);
+    cfg.service(change_email);
+    cfg.service(enroll_totp);
+    cfg.service(confirm_totp);
+    cfg.service(regenerate_recovery_codes);
+    cfg.service(disable_totp);
+    cfg.service(oidc_start);
+    cfg.service(oidc_callback);
+    cfg.service(list_personal_access_tokens);
+    cfg.service(create_personal_access_token);
+    cfg.service(find_personal_access_token);
+    cfg.service(delete_personal_access_token);
+    cfg.service(sessions);
+    cfg.service(revoke_session_by_id);
+}
+
+pub fn init_well_known_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(jwks);
 }
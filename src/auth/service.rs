@@ -1,46 +1,285 @@
-use actix_web::{error::PayloadError, FromRequest, HttpMessage};
-use jwt::VerifyWithKey;
-use std::{collections::BTreeMap, env, error::Error, pin::Pin};
+use actix_web::{http::header::USER_AGENT, FromRequest, HttpMessage, HttpRequest};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use log::error;
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{env, error::Error};
+use uuid::Uuid;
 
-use hmac::{Hmac, Mac};
-use jwt::SignWithKey;
-use sha2::Sha256;
+use crate::{
+    api_error::ApiError,
+    settings::setting,
+    user::{Role, User},
+};
 
-pub fn sign(claims: BTreeMap<&str, &str>) -> Result<String, Box<dyn Error>> {
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET not set");
-    let key: Hmac<Sha256> = Hmac::new_from_slice(secret.as_bytes())?;
+use super::{
+    keys::key_ring,
+    models::LoginResponse,
+    personal_access_token::{PersonalAccessToken, TOKEN_PREFIX},
+    refresh_token::RefreshToken,
+    revoked_token::RevokedToken,
+    session::Session,
+};
 
-    let token_str = claims.sign_with_key(&key)?;
+pub const ACCESS_TOKEN_TYPE: &str = "at+jwt";
 
-    Ok(token_str)
+pub fn sign(claims: &Claims) -> Result<String, Box<dyn Error>> {
+    key_ring().encode(claims, ACCESS_TOKEN_TYPE)
 }
 
-pub fn verify(token_str: &str) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET not set");
-    let key: Hmac<Sha256> = Hmac::new_from_slice(secret.as_bytes())?;
-    
-    let token_str = token_str.replace("Bearer ", "");
+pub fn verify(token_str: &str) -> Result<Claims, Box<dyn Error>> {
+    let token_str = token_str.trim_start_matches("Bearer ");
+
+    if token_str.starts_with(TOKEN_PREFIX) {
+        let token = PersonalAccessToken::authenticate(token_str).map_err(|e| e.to_string())?;
+
+        return Ok(Claims::for_personal_access_token(&token));
+    }
+
+    let claims: Claims = key_ring().decode(token_str, ACCESS_TOKEN_TYPE)?;
+
+    if RevokedToken::is_revoked(claims.jti).map_err(|e| e.to_string())? {
+        return Err("Token has been revoked".into());
+    }
 
-    let claims: BTreeMap<String, String> = token_str.verify_with_key(&key)?;
     Ok(claims)
 }
 
-#[derive(Debug, Clone)]
+/// Verifies the bearer token in the `Authorization` header of `req`.
+pub fn authorize(req: &HttpRequest) -> Result<Claims, ApiError> {
+    let unauthorized = || ApiError::unauthorized("This request is unauthorized".to_string());
+
+    let token = match req.headers().get("Authorization") {
+        Some(token) => token.to_str().map_err(|_| {
+            error!("Token is not a valid string");
+            unauthorized()
+        })?,
+        None => {
+            error!("No token provided");
+            return Err(unauthorized());
+        }
+    };
+
+    verify(token).map_err(|e| {
+        error!("{:?}", e);
+        unauthorized()
+    })
+}
+
+/// IP address of the client sending `req`.
+///
+/// `X-Forwarded-For` and `Forwarded` are only honoured when `TRUST_PROXY_HEADERS`
+/// is set, as anyone can send them otherwise.
+pub fn client_ip(req: &HttpRequest) -> String {
+    if env::var("TRUST_PROXY_HEADERS").as_deref() == Ok("true") {
+        if let Some(ip) = req.connection_info().realip_remote_addr() {
+            return ip.to_string();
+        }
+    }
+
+    req.peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Revokes every session, access and refresh token of `user_id`.
+pub fn logout_everywhere(user_id: Uuid) -> Result<(), ApiError> {
+    RevokedToken::revoke_all(user_id)
+}
+
+/// Starts a new session for `user` on the device `req` came from and returns
+/// the first token pair.
+pub fn login_response(user: &User, req: &HttpRequest) -> Result<LoginResponse, ApiError> {
+    let jti = Uuid::new_v4();
+    let session_id = Uuid::new_v4();
+    let refresh_token = RefreshToken::issue(user.id, session_id, jti)?;
+
+    let user_agent = req
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|user_agent| user_agent.to_str().ok())
+        .map(|user_agent| user_agent.chars().take(512).collect());
+
+    Session::start(session_id, user.id, jti, user_agent, client_ip(req))?;
+
+    token_response(user, jti, session_id, refresh_token)
+}
+
+/// Rotates `refresh_token` and returns a new token pair for its owner.
+pub fn refresh_response(refresh_token: &str) -> Result<LoginResponse, ApiError> {
+    let jti = Uuid::new_v4();
+    let (user_id, session_id, refresh_token) = RefreshToken::rotate(refresh_token, jti)?;
+    let user = User::find(user_id)?;
+    user.ensure_active()?;
+
+    Session::refreshed(session_id, jti)?;
+
+    token_response(&user, jti, session_id, refresh_token)
+}
+
+/// Revokes the access token described by `claims` and ends its session.
+pub fn logout(claims: &Claims) -> Result<(), ApiError> {
+    RevokedToken::revoke(claims.jti, claims.sub, claims.expires_at())?;
+
+    match claims.sid {
+        Some(sid) => {
+            Session::revoke(claims.sub, sid)?;
+        }
+        None => {
+            RefreshToken::revoke_family_of(claims.jti)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Rejects access tokens whose session was revoked since they were issued.
+pub fn check_session(claims: &Claims) -> Result<(), ApiError> {
+    match claims.sid {
+        Some(sid) if !Session::touch(sid)? => Err(ApiError::unauthorized(
+            "Session has been revoked".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn token_response(
+    user: &User,
+    jti: Uuid,
+    session_id: Uuid,
+    refresh_token: String,
+) -> Result<LoginResponse, ApiError> {
+    let ttl = access_token_ttl();
+    let claims = Claims::new(user, jti, session_id, ttl);
+
+    let token = sign(&claims).map_err(|_| ApiError::internal_server_error())?;
+
+    Ok(LoginResponse {
+        token,
+        refresh_token,
+        expires_in: ttl.num_seconds(),
+    })
+}
+
+/// Generates a random opaque token, such as a refresh or password reset token.
+pub fn generate_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect()
+}
+
+/// Hashes an opaque token for storage, so a database leak does not leak usable tokens.
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Converts an `exp` claim into the timestamp the token stops being valid.
+pub fn expiry(exp: i64) -> NaiveDateTime {
+    DateTime::from_timestamp(exp, 0)
+        .map(|expires_at| expires_at.naive_utc())
+        .unwrap_or_else(|| Utc::now().naive_utc())
+}
+
+pub fn access_token_ttl() -> Duration {
+    let seconds = setting("JWT_EXPIRY_SECONDS", 15 * 60);
+
+    Duration::seconds(seconds)
+}
+
+/// Registered claims carried by every access token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
-    pub id: String,
-    pub name: String,
-    pub email: String,
+    pub sub: Uuid,
+    pub exp: i64,
+    pub iat: i64,
+    pub iss: String,
+    pub aud: String,
+    pub jti: Uuid,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// Session the token was issued in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<Uuid>,
+    /// Scopes of the personal access token the request was made with. `None`
+    /// for regular sessions, which are not limited.
+    #[serde(skip)]
+    pub scopes: Option<Vec<String>>,
+}
+
+impl Claims {
+    pub fn new(user: &User, jti: Uuid, sid: Uuid, ttl: Duration) -> Self {
+        let now = Utc::now();
+        let key_ring = key_ring();
+
+        Claims {
+            sub: user.id,
+            exp: (now + ttl).timestamp(),
+            iat: now.timestamp(),
+            iss: key_ring.issuer.clone(),
+            aud: key_ring.audience.clone(),
+            jti,
+            roles: vec![user.role.to_string()],
+            sid: Some(sid),
+            scopes: None,
+        }
+    }
+
+    /// Claims standing in for a personal access token, which is not a JWT.
+    pub fn for_personal_access_token(token: &PersonalAccessToken) -> Self {
+        let key_ring = key_ring();
+
+        Claims {
+            sub: token.user_id,
+            exp: token
+                .expires_at
+                .map_or(i64::MAX, |expires_at| expires_at.and_utc().timestamp()),
+            iat: token.created_at.and_utc().timestamp(),
+            iss: key_ring.issuer.clone(),
+            aud: key_ring.audience.clone(),
+            jti: token.id,
+            roles: Vec::new(),
+            sid: None,
+            scopes: Some(token.scopes.clone()),
+        }
+    }
+
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.iter().any(|granted| granted == role.as_str())
+    }
+
+    /// Rejects personal access tokens that were not granted `scope`.
+    pub fn require_scope(&self, scope: &str) -> Result<(), ApiError> {
+        match &self.scopes {
+            Some(scopes) if !scopes.iter().any(|granted| granted == scope) => Err(
+                ApiError::forbidden(format!("Token is missing the {} scope", scope)),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn expires_at(&self) -> NaiveDateTime {
+        expiry(self.exp)
+    }
 }
 
 impl FromRequest for Claims {
-    type Error = PayloadError;
+    type Error = ApiError;
     type Future = futures::future::LocalBoxFuture<'static, Result<Self, Self::Error>>;
 
-    fn from_request(
-        req: &actix_web::HttpRequest,
-        _payload: &mut actix_web::dev::Payload,
-    ) -> Pin<Box<(dyn futures::Future<Output = Result<Claims, PayloadError>> + 'static)>> {
-        let claims: Claims = req.extensions().get::<Claims>().unwrap().clone();
-        Box::pin(async { Ok(claims) })
+    fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
+        let claims = match req.extensions().get::<Claims>() {
+            Some(claims) => Ok(claims.clone()),
+            None => {
+                error!("No claims found, is the route wrapped with JwtVerifier?");
+                Err(ApiError::unauthorized(
+                    "This request is unauthorized".to_string(),
+                ))
+            }
+        };
+
+        Box::pin(async { claims })
     }
 }
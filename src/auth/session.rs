@@ -0,0 +1,156 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{api_error::ApiError, db, schema::*};
+
+use super::{
+    refresh_token::{self, RefreshToken},
+    revoked_token::RevokedToken,
+    service::access_token_ttl,
+};
+
+/// A device a user logged in from.
+///
+/// A session lives as long as the refresh token family it was started with,
+/// and shares its id.
+#[derive(Serialize, Queryable, Insertable, Debug)]
+#[table_name = "session"]
+pub struct Session {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    /// `jti` of the latest access token issued in the session.
+    #[serde(skip_serializing)]
+    pub access_jti: Uuid,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+impl Session {
+    pub fn start(
+        id: Uuid,
+        user_id: Uuid,
+        access_jti: Uuid,
+        user_agent: Option<String>,
+        ip_address: String,
+    ) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+        let now = Utc::now().naive_utc();
+
+        let session = Session {
+            id,
+            user_id,
+            user_agent,
+            ip_address: Some(ip_address),
+            access_jti,
+            created_at: now,
+            last_seen_at: now,
+            revoked_at: None,
+        };
+
+        let session = diesel::insert_into(session::table)
+            .values(session)
+            .get_result(&mut conn)?;
+
+        Ok(session)
+    }
+
+    /// Records the access token issued when the session's refresh token was rotated.
+    pub fn refreshed(id: Uuid, access_jti: Uuid) -> Result<(), ApiError> {
+        let mut conn = db::connection()?;
+
+        diesel::update(session::table)
+            .filter(session::id.eq(id))
+            .set((
+                session::access_jti.eq(access_jti),
+                session::last_seen_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    /// Every session of `user_id`, including revoked and expired ones.
+    pub fn find_all(user_id: Uuid) -> Result<Vec<Self>, ApiError> {
+        let mut conn = db::connection()?;
+
+        let sessions = session::table
+            .filter(session::user_id.eq(user_id))
+            .order(session::created_at.desc())
+            .load(&mut conn)?;
+
+        Ok(sessions)
+    }
+
+    /// Sessions of `user_id` that were neither revoked nor left to expire.
+    pub fn find_active(user_id: Uuid) -> Result<Vec<Self>, ApiError> {
+        let mut conn = db::connection()?;
+
+        let sessions = session::table
+            .filter(session::user_id.eq(user_id))
+            .filter(session::revoked_at.is_null())
+            .filter(session::last_seen_at.gt(Utc::now().naive_utc() - refresh_token::ttl()))
+            .order(session::last_seen_at.desc())
+            .load(&mut conn)?;
+
+        Ok(sessions)
+    }
+
+    /// Whether session `id` may still be used, bumping its last seen time if so.
+    pub fn touch(id: Uuid) -> Result<bool, ApiError> {
+        let mut conn = db::connection()?;
+        let now = Utc::now().naive_utc();
+
+        let last_seen_at = session::table
+            .filter(session::id.eq(id))
+            .filter(session::revoked_at.is_null())
+            .select(session::last_seen_at)
+            .first::<NaiveDateTime>(&mut conn)
+            .optional()?;
+
+        let last_seen_at = match last_seen_at {
+            Some(last_seen_at) => last_seen_at,
+            None => return Ok(false),
+        };
+
+        // Writing on every request is wasteful, a minute of precision is plenty.
+        if last_seen_at < now - Duration::minutes(1) {
+            diesel::update(session::table)
+                .filter(session::id.eq(id))
+                .set(session::last_seen_at.eq(now))
+                .execute(&mut conn)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Ends session `id` of `user_id`, including the access token currently in use.
+    ///
+    /// Returns `false` when there is no such active session.
+    pub fn revoke(user_id: Uuid, id: Uuid) -> Result<bool, ApiError> {
+        let mut conn = db::connection()?;
+        let now = Utc::now().naive_utc();
+
+        let session = diesel::update(session::table)
+            .filter(session::id.eq(id))
+            .filter(session::user_id.eq(user_id))
+            .filter(session::revoked_at.is_null())
+            .set(session::revoked_at.eq(now))
+            .get_result::<Session>(&mut conn)
+            .optional()?;
+
+        let session = match session {
+            Some(session) => session,
+            None => return Ok(false),
+        };
+
+        RefreshToken::revoke_family(session.id)?;
+        RevokedToken::revoke(session.access_jti, user_id, now + access_token_ttl())?;
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,111 @@
+use std::{env, error::Error, fs::OpenOptions, io::Write};
+
+use lazy_static::lazy_static;
+use lettre::{message::Mailbox, Message, SmtpTransport, Transport};
+use log::info;
+use url::Url;
+
+use crate::api_error::ApiError;
+
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+pub trait Mailer: Send + Sync {
+    fn send(&self, email: &Email) -> Result<(), Box<dyn Error>>;
+}
+
+/// Delivers mail through the SMTP server at `SMTP_URL`.
+pub struct SmtpMailer {
+    transport: SmtpTransport,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(url: &str, from: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(SmtpMailer {
+            transport: SmtpTransport::from_url(url)?.build(),
+            from: from.parse()?,
+        })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, email: &Email) -> Result<(), Box<dyn Error>> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(email.to.parse()?)
+            .subject(email.subject.as_str())
+            .body(email.body.clone())?;
+
+        self.transport.send(&message)?;
+
+        Ok(())
+    }
+}
+
+/// Logs mail instead of sending it, for local development and tests.
+///
+/// When `path` is set every message is also appended to that file.
+pub struct LogMailer {
+    path: Option<String>,
+}
+
+impl LogMailer {
+    pub fn new(path: Option<String>) -> Self {
+        LogMailer { path }
+    }
+}
+
+impl Mailer for LogMailer {
+    fn send(&self, email: &Email) -> Result<(), Box<dyn Error>> {
+        info!("Mail to {}: {}\n{}", email.to, email.subject, email.body);
+
+        if let Some(path) = &self.path {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(
+                file,
+                "To: {}\nSubject: {}\n\n{}\n",
+                email.to, email.subject, email.body
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref MAILER: Box<dyn Mailer> = match env::var("MAILER").as_deref() {
+        Ok("smtp") => {
+            let url = env::var("SMTP_URL").expect("SMTP_URL not set");
+            let from = env::var("MAIL_FROM").expect("MAIL_FROM not set");
+            Box::new(SmtpMailer::new(&url, &from).expect("Failed to create SMTP mailer"))
+        }
+        _ => Box::new(LogMailer::new(env::var("MAIL_LOG_FILE").ok())),
+    };
+    /// Public URL of the app, without a trailing slash.
+    static ref APP_URL: String = {
+        let base = env::var("APP_URL").expect("APP_URL not set");
+        Url::parse(&base).unwrap_or_else(|e| panic!("Invalid APP_URL {}: {}", base, e));
+        base.trim_end_matches('/').to_string()
+    };
+}
+
+pub fn init() {
+    info!("Initializing mailer");
+    lazy_static::initialize(&MAILER);
+    lazy_static::initialize(&APP_URL);
+}
+
+pub fn send(email: Email) -> Result<(), ApiError> {
+    MAILER
+        .send(&email)
+        .map_err(|e| ApiError::new(500, format!("Failed sending email: {}", e)))
+}
+
+/// Builds an absolute link to `path` on the public URL of the app.
+pub fn app_url(path: &str) -> String {
+    format!("{}{}", *APP_URL, path)
+}
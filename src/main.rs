@@ -1,15 +1,21 @@
 // Start server and file watcher with
 // systemfd --no-pid -s http::5000 -- cargo watch -x run
 mod api_error;
+mod audit;
 pub mod auth;
 mod db;
+mod mailer;
+pub mod project;
 mod schema;
+mod settings;
+pub mod tag;
 pub mod todo;
 pub mod user;
 
 use std::{env, io};
 
 use actix_web::{web, App, HttpServer};
+use auth::JwtVerifier;
 use dotenv::dotenv;
 use listenfd::ListenFd;
 use log::info;
@@ -19,16 +25,53 @@ async fn main() -> io::Result<()> {
     dotenv().ok();
     env_logger::init();
     db::init();
+    auth::init();
+    mailer::init();
+    user::role::bootstrap_admins();
+    user::purge::spawn();
 
     let mut listenfd = ListenFd::from_env();
 
     let mut server = HttpServer::new(|| {
         App::new()
-            .configure(user::init_routes)
-            .service(web::scope("/auth").configure(auth::init_routes))
+            .service(
+                web::scope("/auth")
+                    .wrap(
+                        JwtVerifier::new()
+                            .allow("/auth/login")
+                            .allow("/auth/login/mfa")
+                            .allow("/auth/restore-account")
+                            .allow("/auth/register")
+                            .allow("/auth/refresh")
+                            .allow("/auth/verify-email")
+                            .allow("/auth/resend-verification")
+                            .allow("/auth/forgot-password")
+                            .allow("/auth/reset-password")
+                            .allow("/auth/oidc/{provider}/start")
+                            .allow("/auth/oidc/{provider}/callback"),
+                    )
+                    .configure(auth::init_routes),
+            )
+            .service(web::scope("/.well-known").configure(auth::init_well_known_routes))
+            .service(
+                web::scope("/users")
+                    .wrap(JwtVerifier::new())
+                    .configure(user::init_routes),
+            )
             .service(
                 web::scope("/todos")
-                    .configure(todo::init_routes)
+                    .wrap(JwtVerifier::new().personal_access_tokens())
+                    .configure(todo::init_routes),
+            )
+            .service(
+                web::scope("/projects")
+                    .wrap(JwtVerifier::new().personal_access_tokens())
+                    .configure(project::init_routes),
+            )
+            .service(
+                web::scope("/tags")
+                    .wrap(JwtVerifier::new().personal_access_tokens())
+                    .configure(tag::init_routes),
             )
     });
 
@@ -0,0 +1,5 @@
+pub mod model;
+mod routes;
+
+pub use model::Project;
+pub use routes::init_routes;
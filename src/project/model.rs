@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+
+use crate::todo::model::nullable;
+use crate::user::User;
+use crate::{api_error::ApiError, db, schema::*};
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest};
+use chrono::{NaiveDateTime, Utc};
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use futures::future::LocalBoxFuture;
+use log::error;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+/// A list grouping some of the todos of a user.
+#[derive(Serialize, Deserialize, AsChangeset, Insertable, Queryable, Selectable, Debug)]
+#[table_name = "project"]
+#[diesel(treat_none_as_null = true)]
+pub struct Project {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    /// Hex color such as `#3366ff`.
+    pub color: Option<String>,
+    /// Archived projects are hidden and take no new todos.
+    pub archived: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl Project {
+    pub fn create(user: User, form: CreateProjectForm) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let project = Project {
+            id: Uuid::new_v4(),
+            user_id: user.id,
+            name: form.name.unwrap_or_default(),
+            color: form.color.map(|color| color.to_lowercase()),
+            archived: false,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        };
+
+        let project = diesel::insert_into(project::table)
+            .values(project)
+            .returning(Project::as_returning())
+            .get_result(&mut conn)?;
+
+        Ok(project)
+    }
+
+    /// Active or archived projects of `user_id` by name, along with how many
+    /// todos they hold.
+    pub fn find_all(user_id: Uuid, query: &ProjectQuery) -> Result<Vec<ProjectResponse>, ApiError> {
+        let mut conn = db::connection()?;
+
+        let projects = project::table
+            .filter(project::user_id.eq(user_id))
+            .filter(project::archived.eq(query.archived))
+            .order((project::name.asc(), project::id.asc()))
+            .select(Project::as_select())
+            .load::<Project>(&mut conn)?;
+
+        let counts = todo::table
+            .filter(todo::project_id.eq_any(projects.iter().map(|project| project.id)))
+            .group_by((todo::project_id, todo::done))
+            .select((todo::project_id, todo::done, count_star()))
+            .load::<(Option<Uuid>, bool, i64)>(&mut conn)?;
+
+        let mut counts_by_project: HashMap<Uuid, TodoCounts> = HashMap::new();
+        for (project_id, done, todos) in counts {
+            if let Some(project_id) = project_id {
+                counts_by_project
+                    .entry(project_id)
+                    .or_default()
+                    .add(done, todos);
+            }
+        }
+
+        let projects = projects
+            .into_iter()
+            .map(|project| ProjectResponse {
+                counts: counts_by_project.remove(&project.id).unwrap_or_default(),
+                project,
+            })
+            .collect();
+
+        Ok(projects)
+    }
+
+    pub fn find(id: Uuid) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let project = project::table
+            .filter(project::id.eq(id))
+            .select(Project::as_select())
+            .first(&mut conn)?;
+
+        Ok(project)
+    }
+
+    /// How many todos `self` holds.
+    pub fn counts(&self) -> Result<TodoCounts, ApiError> {
+        let mut conn = db::connection()?;
+
+        let counts = todo::table
+            .filter(todo::project_id.eq(self.id))
+            .group_by(todo::done)
+            .select((todo::done, count_star()))
+            .load::<(bool, i64)>(&mut conn)?;
+
+        let mut todo_counts = TodoCounts::default();
+        for (done, todos) in counts {
+            todo_counts.add(done, todos);
+        }
+
+        Ok(todo_counts)
+    }
+
+    /// Checks that todos of `user_id` can be put into project `id`. The project
+    /// is locked until the transaction `conn` is in ends, so that it can not be
+    /// archived or deleted before the todo is saved.
+    pub fn ensure_open(conn: &mut PgConnection, user_id: Uuid, id: Uuid) -> Result<(), ApiError> {
+        match Self::archived(conn, user_id, id)? {
+            None => Err(ApiError::not_found("Project not found".to_string())),
+            Some(true) => Err(ApiError::bad_request(
+                "Todos can not be added to an archived project".to_string(),
+            )),
+            Some(false) => Ok(()),
+        }
+    }
+
+    /// Like [`Project::ensure_open`], but tells whether the project takes new
+    /// todos instead of failing.
+    pub fn is_open(conn: &mut PgConnection, user_id: Uuid, id: Uuid) -> Result<bool, ApiError> {
+        Ok(Self::archived(conn, user_id, id)? == Some(false))
+    }
+
+    /// Whether project `id` of `user_id` is archived, locking it until the
+    /// transaction `conn` is in ends. `None` if there is no such project.
+    fn archived(
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        id: Uuid,
+    ) -> Result<Option<bool>, ApiError> {
+        let archived = project::table
+            .filter(project::id.eq(id))
+            .filter(project::user_id.eq(user_id))
+            .select(project::archived)
+            .for_share()
+            .first::<bool>(conn)
+            .optional()?;
+
+        Ok(archived)
+    }
+
+    pub fn update(user: User, project: Project, form: UpdateProjectForm) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let project = Project {
+            name: form.name.unwrap_or(project.name),
+            color: match form.color {
+                None => project.color,
+                Some(color) => color.map(|color| color.to_lowercase()),
+            },
+            archived: form.archived.unwrap_or(project.archived),
+            updated_at: Some(Utc::now().naive_utc()),
+            ..project
+        };
+
+        let project = diesel::update(project::table)
+            .filter(project::id.eq(project.id))
+            .filter(project::user_id.eq(user.id))
+            .set(project)
+            .returning(Project::as_returning())
+            .get_result(&mut conn)?;
+
+        Ok(project)
+    }
+
+    /// Deletes `project`. Its todos are kept, without a project.
+    pub fn delete(user: User, project: Project) -> Result<usize, ApiError> {
+        let mut conn = db::connection()?;
+
+        let deleted = diesel::delete(project::table)
+            .filter(project::id.eq(project.id))
+            .filter(project::user_id.eq(user.id))
+            .execute(&mut conn)?;
+
+        Ok(deleted)
+    }
+}
+
+impl FromRequest for Project {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let project_id = req.match_info().get("id").unwrap();
+
+        let project_id = match Uuid::parse_str(project_id) {
+            Ok(id) => id,
+            Err(e) => {
+                error!("Error: {}", e);
+                return Box::pin(async {
+                    Err(ApiError::not_found("Invalid id provided".to_string()))
+                });
+            }
+        };
+
+        let project = match Project::find(project_id) {
+            Ok(project) => project,
+            Err(e) => {
+                error!("Error: {}", e);
+                return Box::pin(async {
+                    Err(ApiError::not_found("Project not found".to_string()))
+                });
+            }
+        };
+
+        Box::pin(async { Ok(project) })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct TodoCounts {
+    pub todos: i64,
+    pub done: i64,
+    pub open: i64,
+}
+
+impl TodoCounts {
+    fn add(&mut self, done: bool, todos: i64) {
+        self.todos += todos;
+
+        if done {
+            self.done += todos;
+        } else {
+            self.open += todos;
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ProjectResponse {
+    #[serde(flatten)]
+    pub project: Project,
+    pub counts: TodoCounts,
+}
+
+#[derive(Deserialize)]
+pub struct ProjectQuery {
+    /// Archived projects instead of the active ones when `true`.
+    #[serde(default)]
+    pub archived: bool,
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct CreateProjectForm {
+    #[validate(
+        required(message = "Name is required"),
+        length(min = 1, message = "Name can not be empty")
+    )]
+    pub name: Option<String>,
+    #[validate(custom = "validate_color")]
+    pub color: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct UpdateProjectForm {
+    #[validate(length(min = 1, message = "Name can not be empty"))]
+    pub name: Option<String>,
+    /// Left alone when missing, cleared when `null`.
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(custom = "validate_color")]
+    pub color: Option<Option<String>>,
+    pub archived: Option<bool>,
+}
+
+pub fn validate_color(color: &str) -> Result<(), ValidationError> {
+    let hex = color.strip_prefix('#').unwrap_or_default();
+
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        let mut error = ValidationError::new("color");
+        error.message = Some("Color must be a hex color such as #3366ff".into());
+        return Err(error);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use crate::{
+    api_error::ApiError,
+    auth::{
+        personal_access_token::{TODOS_READ, TODOS_WRITE},
+        Claims,
+    },
+    project::model::{
+        CreateProjectForm, Project, ProjectQuery, ProjectResponse, UpdateProjectForm,
+    },
+    todo::model::{Todo, TodoQuery, TodoResponse},
+    user::User,
+};
+use actix_web::{delete, get, patch, post, web, HttpResponse};
+use serde_json::json;
+use validator::Validate;
+
+#[post("/")]
+async fn create(
+    claims: Claims,
+    user: User,
+    form: web::Json<CreateProjectForm>,
+) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_WRITE)?;
+
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    let project = Project::create(user, form.into_inner())?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Project created successfully",
+        "data": project
+    })))
+}
+
+#[get("/")]
+async fn projects(
+    claims: Claims,
+    user: User,
+    query: web::Query<ProjectQuery>,
+) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_READ)?;
+
+    let projects = Project::find_all(user.id, &query)?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Projects fetched successfully",
+        "data": projects
+    })))
+}
+
+#[get("/{id}")]
+async fn find(claims: Claims, user: User, project: Project) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_READ)?;
+
+    if project.user_id != user.id {
+        return Err(ApiError::unauthorized("Unauthorized".to_string()));
+    }
+
+    let counts = project.counts()?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Project fetched successfully",
+        "data": ProjectResponse { project, counts }
+    })))
+}
+
+#[get("/{id}/todos")]
+async fn todos(
+    claims: Claims,
+    user: User,
+    project: Project,
+    query: web::Query<TodoQuery>,
+) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_READ)?;
+
+    if project.user_id != user.id {
+        return Err(ApiError::unauthorized("Unauthorized".to_string()));
+    }
+
+    match query.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    let mut query = query.into_inner();
+    query.project_id = Some(project.id);
+
+    let page = Todo::find_page(user.id, &query)?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Todos fetched successfully",
+        "data": TodoResponse::from_todos(page.todos)?,
+        "next_cursor": page.next_cursor,
+        "total": page.total
+    })))
+}
+
+#[patch("/{id}")]
+async fn update(
+    claims: Claims,
+    user: User,
+    project: Project,
+    form: web::Json<UpdateProjectForm>,
+) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_WRITE)?;
+
+    if project.user_id != user.id {
+        return Err(ApiError::unauthorized("Unauthorized".to_string()));
+    }
+
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    let project = Project::update(user, project, form.into_inner())?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Project updated successfully",
+        "data": project
+    })))
+}
+
+#[delete("/{id}")]
+async fn delete(claims: Claims, project: Project, user: User) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_WRITE)?;
+
+    if project.user_id != user.id {
+        return Err(ApiError::unauthorized("Unauthorized".to_string()));
+    }
+
+    Project::delete(user, project)?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Project deleted successfully",
+        "data": []
+    })))
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(create);
+    cfg.service(projects);
+    cfg.service(find);
+    cfg.service(todos);
+    cfg.service(update);
+    cfg.service(delete);
+}
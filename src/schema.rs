@@ -1,6 +1,169 @@
 // @generated automatically by Diesel CLI.
 
+pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "tsvector", schema = "pg_catalog"))]
+    pub struct Tsvector;
+}
+
+diesel::table! {
+    audit_event (id) {
+        id -> Uuid,
+        user_id -> Nullable<Uuid>,
+        event -> Text,
+        ip_address -> Nullable<Text>,
+        details -> Jsonb,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    data_export (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        format -> Text,
+        status -> Text,
+        content -> Nullable<Bytea>,
+        error -> Nullable<Text>,
+        created_at -> Timestamp,
+        completed_at -> Nullable<Timestamp>,
+        expires_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    identity (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        provider -> Text,
+        subject -> Text,
+        email -> Nullable<Text>,
+        created_at -> Timestamp,
+        last_login_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    login_attempt (id) {
+        id -> Uuid,
+        user_id -> Nullable<Uuid>,
+        email -> Text,
+        ip_address -> Text,
+        succeeded -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    oidc_auth_request (id) {
+        id -> Uuid,
+        provider -> Text,
+        state -> Text,
+        code_verifier -> Text,
+        nonce -> Text,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    password_reset (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        token_hash -> Text,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    personal_access_token (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        name -> Text,
+        prefix -> Text,
+        token_hash -> Text,
+        scopes -> Array<Text>,
+        last_used_at -> Nullable<Timestamp>,
+        expires_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
+    project (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        name -> Text,
+        color -> Nullable<Text>,
+        archived -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    recovery_code (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        code_hash -> Text,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    refresh_token (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        family_id -> Uuid,
+        token_hash -> Text,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        access_jti -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    revoked_token (jti) {
+        jti -> Uuid,
+        user_id -> Uuid,
+        expires_at -> Timestamp,
+        revoked_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    session (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        user_agent -> Nullable<Text>,
+        ip_address -> Nullable<Text>,
+        access_jti -> Uuid,
+        created_at -> Timestamp,
+        last_seen_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    tag (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        name -> Text,
+        color -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Tsvector;
+
     todo (id) {
         id -> Uuid,
         title -> Text,
@@ -9,6 +172,22 @@ diesel::table! {
         user_id -> Uuid,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
+        search_vector -> Tsvector,
+        due_at -> Nullable<Timestamptz>,
+        remind_at -> Nullable<Timestamptz>,
+        recurrence -> Nullable<Text>,
+        series_id -> Nullable<Uuid>,
+        occurrence -> Int4,
+        priority -> Int2,
+        position -> Text,
+        project_id -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    todo_tag (todo_id, tag_id) {
+        todo_id -> Uuid,
+        tag_id -> Uuid,
     }
 }
 
@@ -20,12 +199,50 @@ diesel::table! {
         password -> Text,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
+        email_verified_at -> Nullable<Timestamp>,
+        account_locked_until -> Nullable<Timestamp>,
+        totp_secret -> Nullable<Text>,
+        totp_enabled_at -> Nullable<Timestamp>,
+        role -> Text,
+        deactivated_at -> Nullable<Timestamp>,
+        deleted_at -> Nullable<Timestamp>,
+        timezone -> Text,
+        totp_last_step -> Nullable<Int8>,
     }
 }
 
+diesel::joinable!(audit_event -> user (user_id));
+diesel::joinable!(data_export -> user (user_id));
+diesel::joinable!(identity -> user (user_id));
+diesel::joinable!(login_attempt -> user (user_id));
+diesel::joinable!(password_reset -> user (user_id));
+diesel::joinable!(personal_access_token -> user (user_id));
+diesel::joinable!(project -> user (user_id));
+diesel::joinable!(recovery_code -> user (user_id));
+diesel::joinable!(refresh_token -> user (user_id));
+diesel::joinable!(revoked_token -> user (user_id));
+diesel::joinable!(session -> user (user_id));
+diesel::joinable!(tag -> user (user_id));
+diesel::joinable!(todo -> project (project_id));
 diesel::joinable!(todo -> user (user_id));
+diesel::joinable!(todo_tag -> tag (tag_id));
+diesel::joinable!(todo_tag -> todo (todo_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    audit_event,
+    data_export,
+    identity,
+    login_attempt,
+    oidc_auth_request,
+    password_reset,
+    personal_access_token,
+    project,
+    recovery_code,
+    refresh_token,
+    revoked_token,
+    session,
+    tag,
     todo,
+    todo_tag,
     user,
 );
@@ -0,0 +1,10 @@
+use std::{env, str::FromStr};
+
+/// Value of the environment variable `name`, or `default` when it is missing or
+/// can not be parsed.
+pub fn setting<T: FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
@@ -0,0 +1,5 @@
+pub mod model;
+mod routes;
+
+pub use model::Tag;
+pub use routes::init_routes;
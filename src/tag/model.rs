@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use crate::project::model::validate_color;
+use crate::todo::model::nullable;
+use crate::user::User;
+use crate::{api_error::ApiError, db, schema::*};
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest};
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use futures::future::LocalBoxFuture;
+use log::error;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+/// A label todos of the same user can be tagged with, such as `bug` or `ops`.
+#[derive(Serialize, Deserialize, AsChangeset, Insertable, Queryable, Selectable, Clone, Debug)]
+#[table_name = "tag"]
+#[diesel(treat_none_as_null = true)]
+pub struct Tag {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    /// Hex color such as `#3366ff`.
+    pub color: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+/// Links a todo to one of its tags.
+#[derive(Insertable, Queryable, Debug)]
+#[table_name = "todo_tag"]
+pub struct TodoTag {
+    pub todo_id: Uuid,
+    pub tag_id: Uuid,
+}
+
+impl Tag {
+    pub fn create(user: User, form: CreateTagForm) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let tag = Tag {
+            id: Uuid::new_v4(),
+            user_id: user.id,
+            name: form.name.unwrap_or_default(),
+            color: form.color.map(|color| color.to_lowercase()),
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        };
+
+        let tag = diesel::insert_into(tag::table)
+            .values(tag)
+            .returning(Tag::as_returning())
+            .get_result(&mut conn)
+            .map_err(name_taken)?;
+
+        Ok(tag)
+    }
+
+    /// Tags of `user_id` by name.
+    pub fn find_all(user_id: Uuid) -> Result<Vec<Self>, ApiError> {
+        let mut conn = db::connection()?;
+
+        let tags = tag::table
+            .filter(tag::user_id.eq(user_id))
+            .order((tag::name.asc(), tag::id.asc()))
+            .select(Tag::as_select())
+            .load(&mut conn)?;
+
+        Ok(tags)
+    }
+
+    pub fn find(id: Uuid) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let tag = tag::table
+            .filter(tag::id.eq(id))
+            .select(Tag::as_select())
+            .first(&mut conn)?;
+
+        Ok(tag)
+    }
+
+    /// Tags of each of `todo_ids` by name, with todos without tags left out.
+    pub fn for_todos(todo_ids: &[Uuid]) -> Result<HashMap<Uuid, Vec<Self>>, ApiError> {
+        let mut conn = db::connection()?;
+
+        let tags = todo_tag::table
+            .inner_join(tag::table)
+            .filter(todo_tag::todo_id.eq_any(todo_ids))
+            .order((tag::name.asc(), tag::id.asc()))
+            .select((todo_tag::todo_id, Tag::as_select()))
+            .load::<(Uuid, Tag)>(&mut conn)?;
+
+        let mut tags_by_todo: HashMap<Uuid, Vec<Self>> = HashMap::new();
+        for (todo_id, tag) in tags {
+            tags_by_todo.entry(todo_id).or_default().push(tag);
+        }
+
+        Ok(tags_by_todo)
+    }
+
+    /// Replaces the tags of todo `todo_id` by `tag_ids`, which must all belong
+    /// to `user_id`.
+    pub fn set_for_todo(
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        todo_id: Uuid,
+        tag_ids: &[Uuid],
+    ) -> Result<(), ApiError> {
+        let mut tag_ids = tag_ids.to_vec();
+        tag_ids.sort();
+        tag_ids.dedup();
+
+        let owned: i64 = tag::table
+            .filter(tag::user_id.eq(user_id))
+            .filter(tag::id.eq_any(&tag_ids))
+            .count()
+            .get_result(conn)?;
+
+        if owned != tag_ids.len() as i64 {
+            return Err(ApiError::not_found("Tag not found".to_string()));
+        }
+
+        diesel::delete(todo_tag::table)
+            .filter(todo_tag::todo_id.eq(todo_id))
+            .execute(conn)?;
+
+        let todo_tags = tag_ids
+            .into_iter()
+            .map(|tag_id| TodoTag { todo_id, tag_id })
+            .collect::<Vec<_>>();
+
+        diesel::insert_into(todo_tag::table)
+            .values(todo_tags)
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Tags todo `to` with the tags of todo `from`.
+    pub fn copy_to_todo(conn: &mut PgConnection, from: Uuid, to: Uuid) -> Result<(), ApiError> {
+        let todo_tags = todo_tag::table
+            .filter(todo_tag::todo_id.eq(from))
+            .select(todo_tag::tag_id)
+            .load::<Uuid>(conn)?
+            .into_iter()
+            .map(|tag_id| TodoTag {
+                todo_id: to,
+                tag_id,
+            })
+            .collect::<Vec<_>>();
+
+        diesel::insert_into(todo_tag::table)
+            .values(todo_tags)
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    pub fn update(user: User, tag: Tag, form: UpdateTagForm) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let tag = Tag {
+            name: form.name.unwrap_or(tag.name),
+            color: match form.color {
+                None => tag.color,
+                Some(color) => color.map(|color| color.to_lowercase()),
+            },
+            updated_at: Some(Utc::now().naive_utc()),
+            ..tag
+        };
+
+        let tag = diesel::update(tag::table)
+            .filter(tag::id.eq(tag.id))
+            .filter(tag::user_id.eq(user.id))
+            .set(tag)
+            .returning(Tag::as_returning())
+            .get_result(&mut conn)
+            .map_err(name_taken)?;
+
+        Ok(tag)
+    }
+
+    /// Deletes `tag`, removing it from the todos tagged with it.
+    pub fn delete(user: User, tag: Tag) -> Result<usize, ApiError> {
+        let mut conn = db::connection()?;
+
+        let deleted = diesel::delete(tag::table)
+            .filter(tag::id.eq(tag.id))
+            .filter(tag::user_id.eq(user.id))
+            .execute(&mut conn)?;
+
+        Ok(deleted)
+    }
+}
+
+/// Explains the unique violation when a user already has a tag of that name.
+fn name_taken(error: diesel::result::Error) -> ApiError {
+    match ApiError::from(error) {
+        e if e.status_code == 409 => {
+            ApiError::new(409, "A tag with this name already exists".to_string())
+        }
+        e => e,
+    }
+}
+
+impl FromRequest for Tag {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let tag_id = req.match_info().get("id").unwrap();
+
+        let tag_id = match Uuid::parse_str(tag_id) {
+            Ok(id) => id,
+            Err(e) => {
+                error!("Error: {}", e);
+                return Box::pin(async {
+                    Err(ApiError::not_found("Invalid id provided".to_string()))
+                });
+            }
+        };
+
+        let tag = match Tag::find(tag_id) {
+            Ok(tag) => tag,
+            Err(e) => {
+                error!("Error: {}", e);
+                return Box::pin(async { Err(ApiError::not_found("Tag not found".to_string())) });
+            }
+        };
+
+        Box::pin(async { Ok(tag) })
+    }
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct CreateTagForm {
+    #[validate(
+        required(message = "Name is required"),
+        length(min = 1, message = "Name can not be empty")
+    )]
+    pub name: Option<String>,
+    #[validate(custom = "validate_color")]
+    pub color: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct UpdateTagForm {
+    #[validate(length(min = 1, message = "Name can not be empty"))]
+    pub name: Option<String>,
+    /// Left alone when missing, cleared when `null`.
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(custom = "validate_color")]
+    pub color: Option<Option<String>>,
+}
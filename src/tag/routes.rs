@@ -0,0 +1,98 @@
+use crate::{
+    api_error::ApiError,
+    auth::{
+        personal_access_token::{TODOS_READ, TODOS_WRITE},
+        Claims,
+    },
+    tag::model::{CreateTagForm, Tag, UpdateTagForm},
+    user::User,
+};
+use actix_web::{delete, get, patch, post, web, HttpResponse};
+use serde_json::json;
+use validator::Validate;
+
+#[post("/")]
+async fn create(
+    claims: Claims,
+    user: User,
+    form: web::Json<CreateTagForm>,
+) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_WRITE)?;
+
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    let tag = Tag::create(user, form.into_inner())?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Tag created successfully",
+        "data": tag
+    })))
+}
+
+#[get("/")]
+async fn tags(claims: Claims, user: User) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_READ)?;
+
+    let tags = Tag::find_all(user.id)?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Tags fetched successfully",
+        "data": tags
+    })))
+}
+
+#[patch("/{id}")]
+async fn update(
+    claims: Claims,
+    user: User,
+    tag: Tag,
+    form: web::Json<UpdateTagForm>,
+) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_WRITE)?;
+
+    if tag.user_id != user.id {
+        return Err(ApiError::unauthorized("Unauthorized".to_string()));
+    }
+
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    let tag = Tag::update(user, tag, form.into_inner())?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Tag updated successfully",
+        "data": tag
+    })))
+}
+
+#[delete("/{id}")]
+async fn delete(claims: Claims, tag: Tag, user: User) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_WRITE)?;
+
+    if tag.user_id != user.id {
+        return Err(ApiError::unauthorized("Unauthorized".to_string()));
+    }
+
+    Tag::delete(user, tag)?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Tag deleted successfully",
+        "data": []
+    })))
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(create);
+    cfg.service(tags);
+    cfg.service(update);
+    cfg.service(delete);
+}
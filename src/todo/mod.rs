@@ -1,4 +1,7 @@
 pub mod model;
+mod position;
+pub mod priority;
+pub mod recurrence;
 mod routes;
 mod service;
 
@@ -1,9 +1,19 @@
+use crate::project::Project;
+use crate::tag::Tag;
+use crate::todo::position;
+use crate::todo::priority::Priority;
+use crate::todo::recurrence::RecurrenceRule;
 use crate::user::User;
 use crate::{api_error::ApiError, db, schema::*};
 use actix_web::dev::Payload;
 use actix_web::{FromRequest, HttpRequest};
-use chrono::{NaiveDateTime, Utc};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
+use diesel::dsl::{count_star, max, min};
+use diesel::pg::Pg;
 use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Float4, Nullable, Text, Timestamp};
 use diesel::{AsChangeset, Insertable, Queryable};
 use futures::future::LocalBoxFuture;
 use log::error;
@@ -11,8 +21,16 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use validator::Validate;
 
-#[derive(Serialize, Deserialize, AsChangeset, Insertable, Queryable, Debug)]
+define_sql_function! {
+    /// Sorts todos that were never updated by when they were created.
+    fn coalesce(x: Nullable<Timestamp>, y: Timestamp) -> Timestamp;
+}
+
+#[derive(
+    Serialize, Deserialize, AsChangeset, Insertable, Queryable, QueryableByName, Selectable, Debug,
+)]
 #[table_name = "todo"]
+#[diesel(treat_none_as_null = true)]
 pub struct Todo {
     pub id: Uuid,
     pub title: String,
@@ -21,19 +39,59 @@ pub struct Todo {
     pub user_id: Uuid,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub remind_at: Option<DateTime<Utc>>,
+    /// RFC 5545 recurrence rule, such as `FREQ=WEEKLY;BYDAY=MO`.
+    pub recurrence: Option<String>,
+    /// Id of the first todo of the series this todo is an occurrence of.
+    pub series_id: Option<Uuid>,
+    /// Position of this todo in its series, starting at 1.
+    pub occurrence: i32,
+    pub priority: Priority,
+    /// Key ordering the todos of a user the way they arranged them.
+    pub position: String,
+    pub project_id: Option<Uuid>,
 }
 
 impl Todo {
-    pub fn create(user: User, todo: CreateTodoForm) -> Result<Self, ApiError> {
+    pub fn create(user: User, mut todo: CreateTodoForm) -> Result<Self, ApiError> {
         let mut conn = db::connection()?;
 
-        let todo = Todo::from(todo).set_user(user);
+        let tag_ids = todo.tag_ids.take().unwrap_or_default();
+        let mut todo = Todo::from(todo).set_user(user);
+        check_reminder(todo.due_at, todo.remind_at)?;
 
-        let todo = diesel::insert_into(todo::table)
-            .values(todo)
-            .get_result(&mut conn)?;
+        todo.recurrence = todo
+            .recurrence
+            .as_deref()
+            .map(normalize_recurrence)
+            .transpose()?;
+        check_recurrence(todo.due_at, todo.recurrence.as_deref())?;
+        todo.series_id = todo.recurrence.as_ref().map(|_| todo.id);
 
-        Ok(todo)
+        conn.transaction::<_, ApiError, _>(|conn| {
+            if let Some(project_id) = todo.project_id {
+                Project::ensure_open(conn, todo.user_id, project_id)?;
+            }
+
+            lock_list(conn, todo.user_id)?;
+
+            // New todos go to the end of the list.
+            let last = todo::table
+                .filter(todo::user_id.eq(todo.user_id))
+                .select(max(todo::position))
+                .first::<Option<String>>(conn)?;
+            todo.position = key_between(last.as_deref(), None)?;
+
+            let todo: Todo = diesel::insert_into(todo::table)
+                .values(todo)
+                .returning(Todo::as_returning())
+                .get_result(conn)?;
+
+            Tag::set_for_todo(conn, todo.user_id, todo.id, &tag_ids)?;
+
+            Ok(todo)
+        })
     }
 
     pub fn todos(user: User) -> Result<Vec<Self>, ApiError> {
@@ -41,7 +99,250 @@ impl Todo {
 
         let todos = todo::table
             .filter(todo::user_id.eq(user.id))
-            .load::<Todo>(&mut conn)?;
+            .select(Todo::as_select())
+            .load(&mut conn)?;
+
+        Ok(todos)
+    }
+
+    /// One page of the todos of `user_id` matching `query`, along with how many
+    /// todos match in total.
+    pub fn find_page(user_id: Uuid, query: &TodoQuery) -> Result<TodoPage, ApiError> {
+        let mut conn = db::connection()?;
+        let limit = query.limit.unwrap_or(50);
+
+        let total = Self::filtered(user_id, query)
+            .count()
+            .get_result(&mut conn)?;
+
+        let mut todos = Self::filtered(user_id, query);
+
+        if let Some(cursor) = &query.cursor {
+            todos = Cursor::decode(cursor, query)?.after(todos)?;
+        }
+
+        todos = match (query.sort, query.order) {
+            (TodoSort::CreatedAt, SortOrder::Asc) => {
+                todos.order((todo::created_at.asc(), todo::id.asc()))
+            }
+            (TodoSort::CreatedAt, SortOrder::Desc) => {
+                todos.order((todo::created_at.desc(), todo::id.desc()))
+            }
+            (TodoSort::UpdatedAt, SortOrder::Asc) => todos.order((
+                coalesce(todo::updated_at, todo::created_at).asc(),
+                todo::id.asc(),
+            )),
+            (TodoSort::UpdatedAt, SortOrder::Desc) => todos.order((
+                coalesce(todo::updated_at, todo::created_at).desc(),
+                todo::id.desc(),
+            )),
+            (TodoSort::Title, SortOrder::Asc) => todos.order((todo::title.asc(), todo::id.asc())),
+            (TodoSort::Title, SortOrder::Desc) => {
+                todos.order((todo::title.desc(), todo::id.desc()))
+            }
+            // Todos without a due date come last either way.
+            (TodoSort::DueAt, SortOrder::Asc) => {
+                todos.order((todo::due_at.asc().nulls_last(), todo::id.asc()))
+            }
+            (TodoSort::DueAt, SortOrder::Desc) => {
+                todos.order((todo::due_at.desc().nulls_last(), todo::id.desc()))
+            }
+            (TodoSort::Priority, SortOrder::Asc) => {
+                todos.order((todo::priority.asc(), todo::id.asc()))
+            }
+            (TodoSort::Priority, SortOrder::Desc) => {
+                todos.order((todo::priority.desc(), todo::id.desc()))
+            }
+            (TodoSort::Position, SortOrder::Asc) => {
+                todos.order((todo::position.asc(), todo::id.asc()))
+            }
+            (TodoSort::Position, SortOrder::Desc) => {
+                todos.order((todo::position.desc(), todo::id.desc()))
+            }
+        };
+
+        // One more than requested tells whether there is a next page.
+        let mut todos = todos
+            .select(Todo::as_select())
+            .limit(limit + 1)
+            .load(&mut conn)?;
+
+        let next_cursor = if todos.len() as i64 > limit {
+            todos.truncate(limit as usize);
+            todos.last().map(|todo| Cursor::new(todo, query).encode())
+        } else {
+            None
+        };
+
+        Ok(TodoPage {
+            todos,
+            next_cursor,
+            total,
+        })
+    }
+
+    /// Todos of `user_id` matching the web search style query `q`, best matches first.
+    pub fn search(user_id: Uuid, query: &SearchQuery) -> Result<Vec<TodoSearchResult>, ApiError> {
+        let mut conn = db::connection()?;
+
+        // Matches are marked with control characters rather than tags, so that
+        // the text around them can be escaped before the tags are put in.
+        let results: Vec<TodoSearchResult> = diesel::sql_query(
+            "SELECT todo.id, todo.title, todo.description, todo.done, todo.user_id, \
+                    todo.created_at, todo.updated_at, todo.due_at, todo.remind_at, \
+                    todo.recurrence, todo.series_id, todo.occurrence, todo.priority, \
+                    todo.position, todo.project_id, \
+                    ts_rank(todo.search_vector, query) AS rank, \
+                    ts_headline('english', todo.title, query, \
+                        'HighlightAll=true, StartSel=\u{2}, StopSel=\u{3}') AS title_highlight, \
+                    ts_headline('english', todo.description, query, \
+                        'MaxFragments=2, StartSel=\u{2}, StopSel=\u{3}') AS snippet \
+             FROM todo, websearch_to_tsquery('english', $1) AS query \
+             WHERE todo.user_id = $2 AND todo.search_vector @@ query \
+             ORDER BY rank DESC, todo.created_at DESC, todo.id \
+             LIMIT $3",
+        )
+        .bind::<Text, _>(query.q.as_deref().unwrap_or_default())
+        .bind::<diesel::sql_types::Uuid, _>(user_id)
+        .bind::<BigInt, _>(query.limit.unwrap_or(20))
+        .load(&mut conn)?;
+
+        let results = results
+            .into_iter()
+            .map(|result| TodoSearchResult {
+                title_highlight: highlight(&result.title_highlight),
+                snippet: highlight(&result.snippet),
+                ..result
+            })
+            .collect();
+
+        Ok(results)
+    }
+
+    fn filtered(user_id: Uuid, query: &TodoQuery) -> todo::BoxedQuery<'static, Pg> {
+        let mut todos = todo::table.filter(todo::user_id.eq(user_id)).into_boxed();
+
+        if let Some(done) = query.done {
+            todos = todos.filter(todo::done.eq(done));
+        }
+
+        if let Some(priority) = query.priority {
+            todos = todos.filter(todo::priority.eq(priority));
+        }
+
+        if let Some(project_id) = query.project_id {
+            todos = todos.filter(todo::project_id.eq(project_id));
+        }
+
+        if let Some(tag_ids) = &query.tags {
+            let tagged = todo_tag::table
+                .filter(todo_tag::tag_id.eq_any(tag_ids.clone()))
+                .select(todo_tag::todo_id);
+
+            todos = match query.tag_match {
+                TagMatch::Any => todos.filter(todo::id.eq_any(tagged)),
+                TagMatch::All => {
+                    let mut tag_ids = tag_ids.clone();
+                    tag_ids.sort();
+                    tag_ids.dedup();
+
+                    todos.filter(
+                        todo::id.eq_any(
+                            tagged
+                                .group_by(todo_tag::todo_id)
+                                .having(count_star().eq(tag_ids.len() as i64)),
+                        ),
+                    )
+                }
+            };
+        }
+
+        if let Some(title) = &query.title {
+            let title = title
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            todos = todos.filter(todo::title.ilike(format!("%{}%", title)));
+        }
+
+        if let Some(created_after) = query.created_after {
+            todos = todos.filter(todo::created_at.ge(created_after));
+        }
+
+        if let Some(created_before) = query.created_before {
+            todos = todos.filter(todo::created_at.lt(created_before));
+        }
+
+        // Todos never updated count as updated when created, as when sorting.
+        if let Some(updated_after) = query.updated_after {
+            todos = todos.filter(coalesce(todo::updated_at, todo::created_at).ge(updated_after));
+        }
+
+        if let Some(updated_before) = query.updated_before {
+            todos = todos.filter(coalesce(todo::updated_at, todo::created_at).lt(updated_before));
+        }
+
+        if let Some(due_after) = query.due_after {
+            todos = todos.filter(todo::due_at.ge(due_after));
+        }
+
+        if let Some(due_before) = query.due_before {
+            todos = todos.filter(todo::due_at.lt(due_before));
+        }
+
+        todos
+    }
+
+    /// Open todos of `user` that are past their due date, oldest first.
+    pub fn overdue(user: User) -> Result<Vec<Self>, ApiError> {
+        Self::due_between(user.id, None, Utc::now())
+    }
+
+    /// Open todos of `user` due today in their timezone.
+    pub fn due_today(user: User) -> Result<Vec<Self>, ApiError> {
+        let tz = user.tz();
+        let today = Utc::now().with_timezone(&tz).date_naive();
+
+        Self::due_between(
+            user.id,
+            Some(start_of_day(tz, today)),
+            start_of_day(tz, today + Duration::days(1)),
+        )
+    }
+
+    /// Open todos of `user` due in the `days` days after today in their timezone.
+    pub fn upcoming(user: User, days: i64) -> Result<Vec<Self>, ApiError> {
+        let tz = user.tz();
+        let tomorrow = Utc::now().with_timezone(&tz).date_naive() + Duration::days(1);
+
+        Self::due_between(
+            user.id,
+            Some(start_of_day(tz, tomorrow)),
+            start_of_day(tz, tomorrow + Duration::days(days)),
+        )
+    }
+
+    fn due_between(
+        user_id: Uuid,
+        from: Option<DateTime<Utc>>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<Self>, ApiError> {
+        let mut conn = db::connection()?;
+
+        let mut todos = todo::table
+            .filter(todo::user_id.eq(user_id))
+            .filter(todo::done.eq(false))
+            .filter(todo::due_at.lt(until))
+            .into_boxed();
+
+        if let Some(from) = from {
+            todos = todos.filter(todo::due_at.ge(from));
+        }
+
+        let todos = todos
+            .order((todo::due_at.asc(), todo::id.asc()))
+            .select(Todo::as_select())
+            .load(&mut conn)?;
 
         Ok(todos)
     }
@@ -49,6 +350,23 @@ impl Todo {
     pub fn update(user: User, todo: Todo, form: UpdateTodoForm) -> Result<Self, ApiError> {
         let mut conn = db::connection()?;
 
+        let due_at = form.due_at.unwrap_or(todo.due_at);
+        let remind_at = form.remind_at.unwrap_or(todo.remind_at);
+        check_reminder(due_at, remind_at)?;
+
+        // Occurrences copy the rule of the one before, so changing or clearing
+        // it on the open occurrence edits or ends the series.
+        let recurrence = match &form.recurrence {
+            None => todo.recurrence.clone(),
+            Some(None) => None,
+            Some(Some(rule)) => Some(normalize_recurrence(rule)?),
+        };
+        check_recurrence(due_at, recurrence.as_deref())?;
+
+        // Moves the todo to another project, or out of its project with `null`.
+        let project_id = form.project_id.unwrap_or(todo.project_id);
+        let new_project_id = project_id.filter(|id| Some(*id) != todo.project_id);
+
         let todo = Todo {
             id: todo.id,
             title: form.title.as_ref().unwrap_or(&todo.title).to_string(),
@@ -61,21 +379,43 @@ impl Todo {
             user_id: todo.user_id,
             created_at: todo.created_at,
             updated_at: Some(Utc::now().naive_utc()),
+            due_at,
+            remind_at,
+            series_id: todo.series_id.or(recurrence.as_ref().map(|_| todo.id)),
+            recurrence,
+            occurrence: todo.occurrence,
+            priority: form.priority.unwrap_or(todo.priority),
+            position: todo.position,
+            project_id,
         };
 
-        let todo = diesel::update(todo::table)
-            .filter(todo::id.eq(todo.id))
-            .filter(todo::user_id.eq(user.id))
-            .set(todo)
-            .get_result(&mut conn)?;
+        conn.transaction::<_, ApiError, _>(|conn| {
+            if let Some(project_id) = new_project_id {
+                Project::ensure_open(conn, user.id, project_id)?;
+            }
 
-        Ok(todo)
+            let todo: Todo = diesel::update(todo::table)
+                .filter(todo::id.eq(todo.id))
+                .filter(todo::user_id.eq(user.id))
+                .set(todo)
+                .returning(Todo::as_returning())
+                .get_result(conn)?;
+
+            if let Some(tag_ids) = &form.tag_ids {
+                Tag::set_for_todo(conn, user.id, todo.id, tag_ids)?;
+            }
+
+            Ok(todo)
+        })
     }
 
     pub fn find(id: Uuid) -> Result<Self, ApiError> {
         let mut conn = db::connection()?;
 
-        let todo = todo::table.filter(todo::id.eq(id)).first(&mut conn)?;
+        let todo = todo::table
+            .filter(todo::id.eq(id))
+            .select(Todo::as_select())
+            .first(&mut conn)?;
 
         Ok(todo)
     }
@@ -91,29 +431,199 @@ impl Todo {
         Ok(deleted)
     }
 
-    pub fn toggle_completion(user: User, todo: Todo) -> Result<Self, ApiError> {
+    /// Flips `done`. Completing an occurrence of a recurring todo creates the
+    /// next one, which is returned along with it.
+    pub fn toggle_completion(user: User, todo: Todo) -> Result<(Self, Option<Self>), ApiError> {
         let mut conn = db::connection()?;
+        let tz = user.tz();
 
-        let todo = Todo {
-            id: todo.id,
-            title: todo.title,
-            description: todo.description,
-            done: !todo.done,
-            user_id: todo.user_id,
-            created_at: todo.created_at,
-            updated_at: Some(Utc::now().naive_utc()),
+        conn.transaction::<_, ApiError, _>(|conn| {
+            let todo = Todo {
+                done: !todo.done,
+                updated_at: Some(Utc::now().naive_utc()),
+                ..todo
+            };
+
+            let todo: Todo = diesel::update(todo::table)
+                .filter(todo::id.eq(todo.id))
+                .filter(todo::user_id.eq(user.id))
+                .set(todo)
+                .returning(Todo::as_returning())
+                .get_result(conn)?;
+
+            let next = match todo.next_occurrence(tz) {
+                Some(next) if todo.done => next,
+                _ => return Ok((todo, None)),
+            };
+
+            // The series leaves its project once that is archived.
+            let project_id = match next.project_id {
+                Some(id) if Project::is_open(conn, todo.user_id, id)? => Some(id),
+                _ => None,
+            };
+
+            // The next occurrence takes the place right after this one.
+            lock_list(conn, todo.user_id)?;
+            let following = neighbour(conn, &todo, &todo.position, false)?;
+            let next = Todo {
+                position: key_between(Some(&todo.position), following.as_deref())?,
+                project_id,
+                ..next
+            };
+
+            // Completing an occurrence again must not repeat the series.
+            let next: Option<Todo> = diesel::insert_into(todo::table)
+                .values(next)
+                .on_conflict((todo::series_id, todo::occurrence))
+                .do_nothing()
+                .returning(Todo::as_returning())
+                .get_result(conn)
+                .optional()?;
+
+            if let Some(next) = &next {
+                Tag::copy_to_todo(conn, todo.id, next.id)?;
+            }
+
+            Ok((todo, next))
+        })
+    }
+
+    /// The todo following this one in its series, unless the series is over.
+    fn next_occurrence(&self, tz: Tz) -> Option<Todo> {
+        let rule = self.recurrence.as_ref()?;
+        let rule = match rule.parse::<RecurrenceRule>() {
+            Ok(rule) => rule,
+            Err(e) => {
+                error!("Invalid recurrence rule of todo {}: {}", self.id, e);
+                return None;
+            }
         };
 
-        let todo = diesel::update(todo::table)
-            .filter(todo::id.eq(todo.id))
-            .filter(todo::user_id.eq(user.id))
-            .set(todo)
-            .get_result(&mut conn)?;
+        let due_at = self.due_at?;
+        let next_due_at = rule.next(due_at, u32::try_from(self.occurrence).ok()?, tz)?;
 
-        Ok(todo)
+        Some(Todo {
+            id: Uuid::new_v4(),
+            title: self.title.clone(),
+            description: self.description.clone(),
+            done: false,
+            user_id: self.user_id,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            due_at: Some(next_due_at),
+            remind_at: self
+                .remind_at
+                .map(|remind_at| next_due_at - (due_at - remind_at)),
+            recurrence: self.recurrence.clone(),
+            series_id: self.series_id.or(Some(self.id)),
+            occurrence: self.occurrence + 1,
+            priority: self.priority,
+            position: self.position.clone(),
+            project_id: self.project_id,
+        })
+    }
+
+    /// Moves `todo` right before or after another todo of `user`, leaving the
+    /// others where they are.
+    pub fn move_to(user: User, todo: Todo, form: MoveTodoForm) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let (target_id, place_before) = match (form.before, form.after) {
+            (Some(before), None) => (before, true),
+            (None, Some(after)) => (after, false),
+            _ => {
+                return Err(ApiError::bad_request(
+                    "Exactly one of before and after is required".to_string(),
+                ))
+            }
+        };
+
+        if target_id == todo.id {
+            return Err(ApiError::bad_request(
+                "A todo can not be moved next to itself".to_string(),
+            ));
+        }
+
+        conn.transaction::<_, ApiError, _>(|conn| {
+            lock_list(conn, user.id)?;
+
+            let target = todo::table
+                .filter(todo::id.eq(target_id))
+                .filter(todo::user_id.eq(user.id))
+                .select(Todo::as_select())
+                .first(conn)
+                .optional()?
+                .ok_or_else(|| ApiError::not_found("Todo not found".to_string()))?;
+
+            let neighbour = neighbour(conn, &todo, &target.position, place_before)?;
+
+            let position = if place_before {
+                key_between(neighbour.as_deref(), Some(&target.position))?
+            } else {
+                key_between(Some(&target.position), neighbour.as_deref())?
+            };
+
+            let todo = diesel::update(todo::table)
+                .filter(todo::id.eq(todo.id))
+                .filter(todo::user_id.eq(user.id))
+                .set((
+                    todo::position.eq(position),
+                    todo::updated_at.eq(Some(Utc::now().naive_utc())),
+                ))
+                .returning(Todo::as_returning())
+                .get_result(conn)?;
+
+            Ok(todo)
+        })
     }
 }
 
+/// Makes other transactions placing todos of `user_id` wait for this one, so
+/// that they don't hand out the same position.
+fn lock_list(conn: &mut PgConnection, user_id: Uuid) -> Result<(), ApiError> {
+    user::table
+        .filter(user::id.eq(user_id))
+        .select(user::id)
+        .for_update()
+        .first::<Uuid>(conn)?;
+
+    Ok(())
+}
+
+/// Position of the todo right before or after `position` in the list of the
+/// owner of `todo`, leaving `todo` itself out.
+fn neighbour(
+    conn: &mut PgConnection,
+    todo: &Todo,
+    position: &str,
+    before: bool,
+) -> Result<Option<String>, ApiError> {
+    let others = todo::table
+        .filter(todo::user_id.eq(todo.user_id))
+        .filter(todo::id.ne(todo.id));
+
+    let neighbour = if before {
+        others
+            .filter(todo::position.lt(position))
+            .select(max(todo::position))
+            .first(conn)?
+    } else {
+        others
+            .filter(todo::position.gt(position))
+            .select(min(todo::position))
+            .first(conn)?
+    };
+
+    Ok(neighbour)
+}
+
+fn key_between(before: Option<&str>, after: Option<&str>) -> Result<String, ApiError> {
+    position::between(before, after).map_err(|e| {
+        error!("Failed generating todo position: {}", e);
+        ApiError::internal_server_error()
+    })
+}
+
 impl Todo {
     pub fn set_user(self, user: User) -> Self {
         Todo {
@@ -157,11 +667,476 @@ pub struct CreateTodoForm {
     #[validate(required(message = "Title is required"))]
     pub title: Option<String>,
     pub description: Option<String>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub remind_at: Option<DateTime<Utc>>,
+    /// RFC 5545 recurrence rule, which requires a due date.
+    pub recurrence: Option<String>,
+    pub priority: Option<Priority>,
+    pub project_id: Option<Uuid>,
+    pub tag_ids: Option<Vec<Uuid>>,
 }
 #[derive(Serialize, Deserialize, Validate)]
 pub struct UpdateTodoForm {
     pub title: Option<String>,
     pub description: Option<String>,
+    /// Left alone when missing, cleared when `null`.
+    #[serde(default, deserialize_with = "nullable")]
+    pub due_at: Option<Option<DateTime<Utc>>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub remind_at: Option<Option<DateTime<Utc>>>,
+    /// `null` ends the series after this occurrence.
+    #[serde(default, deserialize_with = "nullable")]
+    pub recurrence: Option<Option<String>>,
+    pub priority: Option<Priority>,
+    /// `null` takes the todo out of its project.
+    #[serde(default, deserialize_with = "nullable")]
+    pub project_id: Option<Option<Uuid>>,
+    /// Replaces the tags of the todo, so `[]` removes them all.
+    pub tag_ids: Option<Vec<Uuid>>,
+}
+
+/// A todo along with its tags, as handed out by the API.
+#[derive(Serialize)]
+pub struct TodoResponse {
+    #[serde(flatten)]
+    pub todo: Todo,
+    pub tags: Vec<Tag>,
+}
+
+impl TodoResponse {
+    pub fn from_todos(todos: Vec<Todo>) -> Result<Vec<Self>, ApiError> {
+        let ids = todos.iter().map(|todo| todo.id).collect::<Vec<_>>();
+        let mut tags = Tag::for_todos(&ids)?;
+
+        Ok(todos
+            .into_iter()
+            .map(|todo| TodoResponse {
+                tags: tags.remove(&todo.id).unwrap_or_default(),
+                todo,
+            })
+            .collect())
+    }
+
+    pub fn from_todo(todo: Todo) -> Result<Self, ApiError> {
+        let mut todos = Self::from_todos(vec![todo])?;
+        Ok(todos.remove(0))
+    }
+}
+
+/// Where `POST /todos/{id}/move` puts a todo: right before or right after the
+/// todo with the given id.
+#[derive(Serialize, Deserialize)]
+pub struct MoveTodoForm {
+    pub before: Option<Uuid>,
+    pub after: Option<Uuid>,
+}
+
+/// Tells a field set to `null` apart from a missing one.
+pub fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+fn check_reminder(
+    due_at: Option<DateTime<Utc>>,
+    remind_at: Option<DateTime<Utc>>,
+) -> Result<(), ApiError> {
+    match (due_at, remind_at) {
+        (Some(due_at), Some(remind_at)) if remind_at > due_at => Err(ApiError::bad_request(
+            "Reminder must not be after the due date".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Validates `rule` and returns it in its canonical form.
+fn normalize_recurrence(rule: &str) -> Result<String, ApiError> {
+    rule.parse::<RecurrenceRule>()
+        .map(|rule| rule.to_string())
+        .map_err(|e| ApiError::bad_request(format!("Invalid recurrence rule: {}", e)))
+}
+
+fn check_recurrence(
+    due_at: Option<DateTime<Utc>>,
+    recurrence: Option<&str>,
+) -> Result<(), ApiError> {
+    if recurrence.is_some() && due_at.is_none() {
+        return Err(ApiError::bad_request(
+            "Recurring todos need a due date".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// When `date` starts in `tz`. Days that start in a DST gap start at the first
+/// valid time after midnight.
+fn start_of_day(tz: Tz, date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+
+    (0..4)
+        .find_map(|hours| {
+            tz.from_local_datetime(&(midnight + Duration::hours(hours)))
+                .earliest()
+        })
+        .map(|start| start.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&midnight))
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UpcomingQuery {
+    #[validate(range(min = 1, max = 90, message = "Days must be between 1 and 90"))]
+    pub days: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoSort {
+    #[default]
+    CreatedAt,
+    UpdatedAt,
+    Title,
+    DueAt,
+    /// From low to urgent, so usually combined with `order=desc`.
+    Priority,
+    /// The order the user arranged their todos in, with `order=asc`.
+    Position,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Filters, sorting and pagination of `GET /todos`.
+#[derive(Deserialize, Validate)]
+pub struct TodoQuery {
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    #[validate(range(min = 1, max = 100, message = "Limit must be between 1 and 100"))]
+    pub limit: Option<i64>,
+    pub done: Option<bool>,
+    pub priority: Option<Priority>,
+    pub project_id: Option<Uuid>,
+    /// Comma separated ids of tags, of which todos need any or all depending
+    /// on `tag_match`.
+    #[serde(default, deserialize_with = "comma_separated")]
+    pub tags: Option<Vec<Uuid>>,
+    #[serde(default)]
+    pub tag_match: TagMatch,
+    /// Only todos whose title contains this, ignoring case.
+    pub title: Option<String>,
+    pub created_after: Option<NaiveDateTime>,
+    pub created_before: Option<NaiveDateTime>,
+    pub updated_after: Option<NaiveDateTime>,
+    pub updated_before: Option<NaiveDateTime>,
+    pub due_after: Option<DateTime<Utc>>,
+    pub due_before: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub sort: TodoSort,
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatch {
+    #[default]
+    Any,
+    All,
+}
+
+fn comma_separated<'de, D>(deserializer: D) -> Result<Option<Vec<Uuid>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let ids = String::deserialize(deserializer)?
+        .split(',')
+        .filter(|id| !id.trim().is_empty())
+        .map(|id| Uuid::parse_str(id.trim()).map_err(serde::de::Error::custom))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // An empty `tags=` filters nothing rather than everything.
+    Ok(Some(ids).filter(|ids| !ids.is_empty()))
+}
+
+#[derive(Deserialize, Validate)]
+pub struct SearchQuery {
+    #[validate(
+        required(message = "Search query is required"),
+        length(min = 1, message = "Search query is required")
+    )]
+    pub q: Option<String>,
+    #[validate(range(min = 1, max = 100, message = "Limit must be between 1 and 100"))]
+    pub limit: Option<i64>,
+}
+
+/// A todo matching a search, with the matching words wrapped in `<mark>` tags
+/// and the rest of `title_highlight` and `snippet` HTML-escaped.
+#[derive(Serialize, QueryableByName)]
+pub struct TodoSearchResult {
+    #[diesel(embed)]
+    #[serde(flatten)]
+    pub todo: Todo,
+    #[diesel(sql_type = Float4)]
+    pub rank: f32,
+    #[diesel(sql_type = Text)]
+    pub title_highlight: String,
+    /// The best fragments of the description.
+    #[diesel(sql_type = Text)]
+    pub snippet: String,
+}
+
+/// HTML-escapes a `ts_headline` result and wraps the marked matches in
+/// `<mark>` tags.
+fn highlight(headline: &str) -> String {
+    let mut html = String::with_capacity(headline.len());
+
+    for c in headline.chars() {
+        match c {
+            '\u{2}' => html.push_str("<mark>"),
+            '\u{3}' => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+
+    html
+}
+
+/// A search result along with the tags of its todo, as handed out by the API.
+#[derive(Serialize)]
+pub struct TodoSearchResponse {
+    #[serde(flatten)]
+    pub result: TodoSearchResult,
+    pub tags: Vec<Tag>,
+}
+
+impl TodoSearchResponse {
+    pub fn from_results(results: Vec<TodoSearchResult>) -> Result<Vec<Self>, ApiError> {
+        let ids = results
+            .iter()
+            .map(|result| result.todo.id)
+            .collect::<Vec<_>>();
+        let mut tags = Tag::for_todos(&ids)?;
+
+        Ok(results
+            .into_iter()
+            .map(|result| TodoSearchResponse {
+                tags: tags.remove(&result.todo.id).unwrap_or_default(),
+                result,
+            })
+            .collect())
+    }
+}
+
+pub struct TodoPage {
+    pub todos: Vec<Todo>,
+    pub next_cursor: Option<String>,
+    pub total: i64,
+}
+
+/// Sort key and id of the last todo of a page. Keyset pagination stays stable
+/// while todos are added or removed, unlike offsets.
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    sort: TodoSort,
+    order: SortOrder,
+    /// Missing for todos without a due date when sorting by it.
+    value: Option<String>,
+    id: Uuid,
+}
+
+impl Cursor {
+    fn new(todo: &Todo, query: &TodoQuery) -> Self {
+        let value = match query.sort {
+            TodoSort::CreatedAt => Some(format_timestamp(todo.created_at)),
+            TodoSort::UpdatedAt => {
+                Some(format_timestamp(todo.updated_at.unwrap_or(todo.created_at)))
+            }
+            TodoSort::Title => Some(todo.title.clone()),
+            TodoSort::DueAt => todo
+                .due_at
+                .map(|due_at| due_at.to_rfc3339_opts(SecondsFormat::Micros, true)),
+            TodoSort::Priority => Some(todo.priority.to_string()),
+            TodoSort::Position => Some(todo.position.clone()),
+        };
+
+        Cursor {
+            sort: query.sort,
+            order: query.order,
+            value,
+            id: todo.id,
+        }
+    }
+
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    fn decode(cursor: &str, query: &TodoQuery) -> Result<Self, ApiError> {
+        let invalid = || ApiError::bad_request("Invalid cursor".to_string());
+
+        let cursor: Cursor = URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|cursor| serde_json::from_slice(&cursor).ok())
+            .ok_or_else(invalid)?;
+
+        if cursor.sort != query.sort || cursor.order != query.order {
+            return Err(ApiError::bad_request(
+                "Cursor was issued for a different sort order".to_string(),
+            ));
+        }
+
+        Ok(cursor)
+    }
+
+    /// Narrows `todos` down to the ones sorted after this cursor.
+    fn after(
+        self,
+        todos: todo::BoxedQuery<'static, Pg>,
+    ) -> Result<todo::BoxedQuery<'static, Pg>, ApiError> {
+        let id = self.id;
+
+        if self.sort == TodoSort::DueAt {
+            return Ok(match (self.value, self.order) {
+                (Some(value), SortOrder::Asc) => {
+                    let value = parse_due_at(&value)?;
+                    todos.filter(
+                        todo::due_at
+                            .gt(value)
+                            .or(todo::due_at.eq(value).and(todo::id.gt(id)))
+                            .or(todo::due_at.is_null()),
+                    )
+                }
+                (Some(value), SortOrder::Desc) => {
+                    let value = parse_due_at(&value)?;
+                    todos.filter(
+                        todo::due_at
+                            .lt(value)
+                            .or(todo::due_at.eq(value).and(todo::id.lt(id)))
+                            .or(todo::due_at.is_null()),
+                    )
+                }
+                (None, SortOrder::Asc) => todos.filter(todo::due_at.is_null().and(todo::id.gt(id))),
+                (None, SortOrder::Desc) => {
+                    todos.filter(todo::due_at.is_null().and(todo::id.lt(id)))
+                }
+            });
+        }
+
+        let value = self
+            .value
+            .ok_or_else(|| ApiError::bad_request("Invalid cursor".to_string()))?;
+
+        let todos = match (self.sort, self.order) {
+            (TodoSort::CreatedAt, SortOrder::Asc) => {
+                let value = parse_timestamp(&value)?;
+                todos.filter(
+                    todo::created_at
+                        .gt(value)
+                        .or(todo::created_at.eq(value).and(todo::id.gt(id))),
+                )
+            }
+            (TodoSort::CreatedAt, SortOrder::Desc) => {
+                let value = parse_timestamp(&value)?;
+                todos.filter(
+                    todo::created_at
+                        .lt(value)
+                        .or(todo::created_at.eq(value).and(todo::id.lt(id))),
+                )
+            }
+            (TodoSort::UpdatedAt, SortOrder::Asc) => {
+                let value = parse_timestamp(&value)?;
+                let updated_at = || coalesce(todo::updated_at, todo::created_at);
+                todos.filter(
+                    updated_at()
+                        .gt(value)
+                        .or(updated_at().eq(value).and(todo::id.gt(id))),
+                )
+            }
+            (TodoSort::UpdatedAt, SortOrder::Desc) => {
+                let value = parse_timestamp(&value)?;
+                let updated_at = || coalesce(todo::updated_at, todo::created_at);
+                todos.filter(
+                    updated_at()
+                        .lt(value)
+                        .or(updated_at().eq(value).and(todo::id.lt(id))),
+                )
+            }
+            (TodoSort::Title, SortOrder::Asc) => todos.filter(
+                todo::title
+                    .gt(value.clone())
+                    .or(todo::title.eq(value).and(todo::id.gt(id))),
+            ),
+            (TodoSort::Title, SortOrder::Desc) => todos.filter(
+                todo::title
+                    .lt(value.clone())
+                    .or(todo::title.eq(value).and(todo::id.lt(id))),
+            ),
+            (TodoSort::Priority, SortOrder::Asc) => {
+                let value = parse_priority(&value)?;
+                todos.filter(
+                    todo::priority
+                        .gt(value)
+                        .or(todo::priority.eq(value).and(todo::id.gt(id))),
+                )
+            }
+            (TodoSort::Priority, SortOrder::Desc) => {
+                let value = parse_priority(&value)?;
+                todos.filter(
+                    todo::priority
+                        .lt(value)
+                        .or(todo::priority.eq(value).and(todo::id.lt(id))),
+                )
+            }
+            (TodoSort::Position, SortOrder::Asc) => todos.filter(
+                todo::position
+                    .gt(value.clone())
+                    .or(todo::position.eq(value).and(todo::id.gt(id))),
+            ),
+            (TodoSort::Position, SortOrder::Desc) => todos.filter(
+                todo::position
+                    .lt(value.clone())
+                    .or(todo::position.eq(value).and(todo::id.lt(id))),
+            ),
+            (TodoSort::DueAt, _) => unreachable!("due dates are handled above"),
+        };
+
+        Ok(todos)
+    }
+}
+
+const CURSOR_TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+fn format_timestamp(timestamp: NaiveDateTime) -> String {
+    timestamp.format(CURSOR_TIMESTAMP_FORMAT).to_string()
+}
+
+fn parse_timestamp(timestamp: &str) -> Result<NaiveDateTime, ApiError> {
+    NaiveDateTime::parse_from_str(timestamp, CURSOR_TIMESTAMP_FORMAT)
+        .map_err(|_| ApiError::bad_request("Invalid cursor".to_string()))
+}
+
+fn parse_due_at(due_at: &str) -> Result<DateTime<Utc>, ApiError> {
+    DateTime::parse_from_rfc3339(due_at)
+        .map(|due_at| due_at.with_timezone(&Utc))
+        .map_err(|_| ApiError::bad_request("Invalid cursor".to_string()))
+}
+
+fn parse_priority(priority: &str) -> Result<Priority, ApiError> {
+    Priority::parse(priority).ok_or_else(|| ApiError::bad_request("Invalid cursor".to_string()))
 }
 
 impl From<CreateTodoForm> for Todo {
@@ -174,6 +1149,14 @@ impl From<CreateTodoForm> for Todo {
             created_at: chrono::Utc::now().naive_utc(),
             updated_at: None,
             user_id: Uuid::new_v4(),
+            due_at: todo.due_at,
+            remind_at: todo.remind_at,
+            recurrence: todo.recurrence,
+            series_id: None,
+            occurrence: 1,
+            priority: todo.priority.unwrap_or_default(),
+            position: String::new(),
+            project_id: todo.project_id,
         }
     }
 }
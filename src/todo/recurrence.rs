@@ -0,0 +1,581 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{
+    DateTime, Datelike, Duration, Months, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday,
+};
+use chrono_tz::Tz;
+
+/// Upper bound on the periods searched for the next occurrence, so that rules
+/// matching no date at all, such as the 31st of every other February, end.
+const MAX_PERIODS: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A weekday of `BYDAY`, optionally the nth one of the month such as `2MO` or `-1FR`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByDay {
+    pub nth: Option<i8>,
+    pub weekday: Weekday,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Until {
+    Date(NaiveDate),
+    DateTime(DateTime<Utc>),
+}
+
+/// The part of RFC 5545 recurrence rules todos support: `FREQ`, `INTERVAL`,
+/// `BYDAY`, `COUNT` and `UNTIL`.
+///
+/// Occurrences keep the local time of day of the first one, in the timezone of
+/// the todo's owner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecurrenceRule {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub by_day: Vec<ByDay>,
+    pub count: Option<u32>,
+    pub until: Option<Until>,
+}
+
+impl RecurrenceRule {
+    /// The occurrence following `previous`, the `occurrence`th of the series,
+    /// or `None` once the series is over.
+    pub fn next(&self, previous: DateTime<Utc>, occurrence: u32, tz: Tz) -> Option<DateTime<Utc>> {
+        if self.count.is_some_and(|count| occurrence >= count) {
+            return None;
+        }
+
+        let local = previous.with_timezone(&tz).naive_local();
+        let date = self.next_date(local.date())?;
+        let next = localize(tz, date.and_time(local.time()));
+
+        match self.until {
+            Some(Until::Date(until)) if date > until => None,
+            Some(Until::DateTime(until)) if next > until => None,
+            _ => Some(next),
+        }
+    }
+
+    fn next_date(&self, date: NaiveDate) -> Option<NaiveDate> {
+        let interval = self.interval;
+
+        match self.frequency {
+            // With daily rules `BYDAY` only limits which days count.
+            Frequency::Daily => (1..=MAX_PERIODS)
+                .map(|n| date + Duration::days((n * interval).into()))
+                .find(|date| self.on_weekday(date.weekday())),
+            Frequency::Weekly if self.by_day.is_empty() => {
+                Some(date + Duration::weeks(interval.into()))
+            }
+            Frequency::Weekly => {
+                let monday = date - Duration::days(date.weekday().num_days_from_monday().into());
+
+                (0..MAX_PERIODS)
+                    .flat_map(|n| {
+                        let week = monday + Duration::weeks((n * interval).into());
+                        (0..7).map(move |day| week + Duration::days(day))
+                    })
+                    .find(|day| *day > date && self.on_weekday(day.weekday()))
+            }
+            Frequency::Monthly => {
+                let month = date.with_day(1)?;
+
+                (0..MAX_PERIODS).find_map(|n| {
+                    let month = month.checked_add_months(Months::new(n * interval))?;
+                    self.days_in_month(month, date.day())
+                        .into_iter()
+                        .find(|day| *day > date)
+                })
+            }
+            // Leap days are skipped in the years without one.
+            Frequency::Yearly => (1..=MAX_PERIODS).find_map(|n| {
+                let year = date.year() + i32::try_from(n * interval).ok()?;
+                NaiveDate::from_ymd_opt(year, date.month(), date.day())
+            }),
+        }
+    }
+
+    fn on_weekday(&self, weekday: Weekday) -> bool {
+        self.by_day.is_empty() || self.by_day.iter().any(|day| day.weekday == weekday)
+    }
+
+    /// Days of the month starting at `month` that occurrences fall on, in order.
+    fn days_in_month(&self, month: NaiveDate, day: u32) -> Vec<NaiveDate> {
+        if self.by_day.is_empty() {
+            // Months that are too short are skipped rather than clamped.
+            return month.with_day(day).into_iter().collect();
+        }
+
+        let mut days = self
+            .by_day
+            .iter()
+            .flat_map(|by_day| {
+                let weekdays = (1..=31)
+                    .filter_map(|day| month.with_day(day))
+                    .filter(|date| date.weekday() == by_day.weekday)
+                    .collect::<Vec<_>>();
+
+                match by_day.nth {
+                    None => weekdays,
+                    Some(nth) if nth > 0 => weekdays
+                        .get(nth as usize - 1)
+                        .copied()
+                        .into_iter()
+                        .collect(),
+                    Some(nth) => weekdays
+                        .len()
+                        .checked_sub(nth.unsigned_abs() as usize)
+                        .map(|index| weekdays[index])
+                        .into_iter()
+                        .collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        days.sort();
+        days.dedup();
+        days
+    }
+}
+
+/// `local` in `tz`, moved past the gap when clocks skip it.
+fn localize(tz: Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    (0..4)
+        .find_map(|hours| {
+            tz.from_local_datetime(&(local + Duration::hours(hours)))
+                .earliest()
+        })
+        .map(|date| date.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&local))
+}
+
+impl FromStr for RecurrenceRule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let rule = rule.trim();
+        let rule = match rule.get(..6) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("RRULE:") => &rule[6..],
+            _ => rule,
+        };
+
+        let mut frequency = None;
+        let mut interval = 1;
+        let mut by_day = Vec::new();
+        let mut count = None;
+        let mut until = None;
+
+        for part in rule.split(';').filter(|part| !part.is_empty()) {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("expected NAME=VALUE, found {}", part))?;
+            let value = value.to_ascii_uppercase();
+
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(format!("unsupported FREQ {}", value)),
+                    })
+                }
+                "INTERVAL" => {
+                    interval = value
+                        .parse()
+                        .ok()
+                        .filter(|interval| (1..=1000).contains(interval))
+                        .ok_or("INTERVAL must be between 1 and 1000")?
+                }
+                "COUNT" => {
+                    count = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|count| *count > 0)
+                            .ok_or("COUNT must be a positive number")?,
+                    )
+                }
+                "UNTIL" => until = Some(parse_until(&value)?),
+                "BYDAY" => {
+                    by_day = value
+                        .split(',')
+                        .map(parse_by_day)
+                        .collect::<Result<_, _>>()?
+                }
+                // Weeks start on Monday, which is the default anyway.
+                "WKST" if value == "MO" => (),
+                name => return Err(format!("{} is not supported", name)),
+            }
+        }
+
+        let frequency = frequency.ok_or("FREQ is required")?;
+
+        if count.is_some() && until.is_some() {
+            return Err("COUNT and UNTIL can not be combined".to_string());
+        }
+
+        if frequency == Frequency::Yearly && !by_day.is_empty() {
+            return Err("BYDAY is not supported with FREQ=YEARLY".to_string());
+        }
+
+        if frequency != Frequency::Monthly && by_day.iter().any(|day| day.nth.is_some()) {
+            return Err("numbered BYDAY values such as 2MO require FREQ=MONTHLY".to_string());
+        }
+
+        Ok(RecurrenceRule {
+            frequency,
+            interval,
+            by_day,
+            count,
+            until,
+        })
+    }
+}
+
+fn parse_until(until: &str) -> Result<Until, String> {
+    let invalid = || {
+        format!(
+            "UNTIL must look like 20261231 or 20261231T235959Z, found {}",
+            until
+        )
+    };
+
+    match until.strip_suffix('Z') {
+        Some(until) => NaiveDateTime::parse_from_str(until, "%Y%m%dT%H%M%S")
+            .map(|until| Until::DateTime(Utc.from_utc_datetime(&until)))
+            .map_err(|_| invalid()),
+        None => NaiveDate::parse_from_str(until, "%Y%m%d")
+            .map(Until::Date)
+            .map_err(|_| invalid()),
+    }
+}
+
+fn parse_by_day(day: &str) -> Result<ByDay, String> {
+    let invalid = || format!("invalid BYDAY value {}", day);
+
+    // Offsets are in bytes, which need not fall between characters of user input.
+    let split = day.len().checked_sub(2).ok_or_else(invalid)?;
+    let (nth, weekday) = day.get(..split).zip(day.get(split..)).ok_or_else(invalid)?;
+
+    let weekday = match weekday {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return Err(invalid()),
+    };
+
+    let nth = match nth {
+        "" => None,
+        nth => Some(
+            nth.parse::<i8>()
+                .ok()
+                .filter(|nth| *nth != 0 && nth.abs() <= 5)
+                .ok_or_else(invalid)?,
+        ),
+    };
+
+    Ok(ByDay { nth, weekday })
+}
+
+impl fmt::Display for RecurrenceRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let frequency = match self.frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        };
+
+        write!(f, "FREQ={}", frequency)?;
+
+        if self.interval > 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+
+        if !self.by_day.is_empty() {
+            let by_day = self
+                .by_day
+                .iter()
+                .map(|day| {
+                    let weekday = match day.weekday {
+                        Weekday::Mon => "MO",
+                        Weekday::Tue => "TU",
+                        Weekday::Wed => "WE",
+                        Weekday::Thu => "TH",
+                        Weekday::Fri => "FR",
+                        Weekday::Sat => "SA",
+                        Weekday::Sun => "SU",
+                    };
+
+                    match day.nth {
+                        Some(nth) => format!("{}{}", nth, weekday),
+                        None => weekday.to_string(),
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            write!(f, ";BYDAY={}", by_day.join(","))?;
+        }
+
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={}", count)?;
+        }
+
+        match self.until {
+            Some(Until::Date(until)) => write!(f, ";UNTIL={}", until.format("%Y%m%d")),
+            Some(Until::DateTime(until)) => write!(f, ";UNTIL={}", until.format("%Y%m%dT%H%M%SZ")),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(rule: &str) -> RecurrenceRule {
+        rule.parse().unwrap()
+    }
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    /// The occurrences after `first`, until the series ends or `limit` is reached.
+    fn occurrences(
+        rule: &RecurrenceRule,
+        first: DateTime<Utc>,
+        tz: Tz,
+        limit: usize,
+    ) -> Vec<DateTime<Utc>> {
+        let mut occurrences = vec![first];
+
+        while occurrences.len() <= limit {
+            let previous = *occurrences.last().unwrap();
+            match rule.next(previous, occurrences.len() as u32, tz) {
+                Some(next) => occurrences.push(next),
+                None => break,
+            }
+        }
+
+        occurrences.split_off(1)
+    }
+
+    #[test]
+    fn round_trips_through_display() {
+        for (input, canonical) in [
+            ("FREQ=DAILY", "FREQ=DAILY"),
+            ("RRULE:FREQ=DAILY;INTERVAL=1", "FREQ=DAILY"),
+            (
+                "rrule:freq=weekly;interval=2;byday=mo,we,fr",
+                "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,WE,FR",
+            ),
+            (
+                "FREQ=MONTHLY;BYDAY=-1FR;COUNT=5",
+                "FREQ=MONTHLY;BYDAY=-1FR;COUNT=5",
+            ),
+            ("FREQ=MONTHLY;BYDAY=2mo;WKST=MO", "FREQ=MONTHLY;BYDAY=2MO"),
+            ("FREQ=YEARLY;UNTIL=20301231", "FREQ=YEARLY;UNTIL=20301231"),
+            (
+                "FREQ=DAILY;UNTIL=20261231T235959Z;",
+                "FREQ=DAILY;UNTIL=20261231T235959Z",
+            ),
+        ] {
+            let parsed = rule(input);
+            assert_eq!(parsed.to_string(), canonical, "{}", input);
+            assert_eq!(rule(canonical), parsed, "{}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_rules() {
+        for input in [
+            "",
+            "INTERVAL=2",
+            "FREQ=HOURLY",
+            "FREQ=DAILY;COUNT=3;UNTIL=20261231",
+            "FREQ=WEEKLY;BYDAY=2MO",
+            "FREQ=DAILY;BYDAY=-1FR",
+            "FREQ=YEARLY;BYDAY=MO",
+            "FREQ=DAILY;INTERVAL=0",
+            "FREQ=DAILY;INTERVAL=1001",
+            "FREQ=DAILY;COUNT=0",
+            "FREQ=MONTHLY;BYDAY=6MO",
+            "FREQ=WEEKLY;BYDAY=XX",
+            "FREQ=DAILY;UNTIL=2026-12-31",
+            "FREQ=MONTHLY;BYMONTHDAY=1",
+            "FREQ",
+        ] {
+            assert!(input.parse::<RecurrenceRule>().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_non_ascii_weekdays() {
+        for input in [
+            "FREQ=WEEKLY;BYDAY=€",
+            "FREQ=WEEKLY;BYDAY=1€",
+            "FREQ=WEEKLY;BYDAY=MÖ",
+        ] {
+            assert!(input.parse::<RecurrenceRule>().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn weekly_by_day_with_interval() {
+        let rule = rule("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,WE,FR");
+
+        // 2026-10-19 is a Monday, the week after it is skipped.
+        assert_eq!(
+            occurrences(&rule, utc(2026, 10, 19, 9, 0), Tz::UTC, 5),
+            vec![
+                utc(2026, 10, 21, 9, 0),
+                utc(2026, 10, 23, 9, 0),
+                utc(2026, 11, 2, 9, 0),
+                utc(2026, 11, 4, 9, 0),
+                utc(2026, 11, 6, 9, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn monthly_on_the_31st_skips_short_months() {
+        let rule = rule("FREQ=MONTHLY");
+
+        assert_eq!(
+            occurrences(&rule, utc(2026, 1, 31, 9, 0), Tz::UTC, 4),
+            vec![
+                utc(2026, 3, 31, 9, 0),
+                utc(2026, 5, 31, 9, 0),
+                utc(2026, 7, 31, 9, 0),
+                utc(2026, 8, 31, 9, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn monthly_on_numbered_weekdays() {
+        assert_eq!(
+            occurrences(
+                &rule("FREQ=MONTHLY;BYDAY=-1FR"),
+                utc(2026, 10, 1, 9, 0),
+                Tz::UTC,
+                3
+            ),
+            vec![
+                utc(2026, 10, 30, 9, 0),
+                utc(2026, 11, 27, 9, 0),
+                utc(2026, 12, 25, 9, 0),
+            ]
+        );
+
+        assert_eq!(
+            occurrences(
+                &rule("FREQ=MONTHLY;BYDAY=2MO"),
+                utc(2026, 10, 12, 9, 0),
+                Tz::UTC,
+                2
+            ),
+            vec![utc(2026, 11, 9, 9, 0), utc(2026, 12, 14, 9, 0)]
+        );
+    }
+
+    #[test]
+    fn yearly_from_leap_day() {
+        assert_eq!(
+            occurrences(&rule("FREQ=YEARLY"), utc(2024, 2, 29, 9, 0), Tz::UTC, 2),
+            vec![utc(2028, 2, 29, 9, 0), utc(2032, 2, 29, 9, 0)]
+        );
+    }
+
+    #[test]
+    fn count_ends_the_series() {
+        let rule = rule("FREQ=DAILY;COUNT=3");
+        let first = utc(2026, 10, 20, 9, 0);
+
+        assert_eq!(rule.next(first, 2, Tz::UTC), Some(utc(2026, 10, 21, 9, 0)));
+        assert_eq!(rule.next(first, 3, Tz::UTC), None);
+        assert_eq!(occurrences(&rule, first, Tz::UTC, 10).len(), 2);
+    }
+
+    #[test]
+    fn until_date_includes_the_whole_day() {
+        let rule = rule("FREQ=DAILY;UNTIL=20261021");
+
+        assert_eq!(
+            occurrences(&rule, utc(2026, 10, 19, 23, 30), Tz::UTC, 10),
+            vec![utc(2026, 10, 20, 23, 30), utc(2026, 10, 21, 23, 30)]
+        );
+    }
+
+    #[test]
+    fn until_date_time_is_exact() {
+        let rule = rule("FREQ=DAILY;UNTIL=20261021T090000Z");
+
+        assert_eq!(
+            occurrences(&rule, utc(2026, 10, 19, 9, 0), Tz::UTC, 10),
+            vec![utc(2026, 10, 20, 9, 0), utc(2026, 10, 21, 9, 0)]
+        );
+        assert_eq!(rule.next(utc(2026, 10, 20, 9, 1), 1, Tz::UTC), None);
+    }
+
+    #[test]
+    fn keeps_local_time_across_dst_changes() {
+        let berlin = chrono_tz::Europe::Berlin;
+        let rule = rule("FREQ=DAILY");
+
+        // 09:00 CEST, then 09:00 CET after clocks go back on 2026-10-25.
+        assert_eq!(
+            rule.next(utc(2026, 10, 24, 7, 0), 1, berlin),
+            Some(utc(2026, 10, 25, 8, 0))
+        );
+
+        // 09:00 CET, then 09:00 CEST after clocks go forward on 2026-03-29.
+        assert_eq!(
+            rule.next(utc(2026, 3, 28, 8, 0), 1, berlin),
+            Some(utc(2026, 3, 29, 7, 0))
+        );
+    }
+
+    #[test]
+    fn localize_moves_past_gaps_and_picks_the_earlier_overlap() {
+        let berlin = chrono_tz::Europe::Berlin;
+        let local = |month, day, hour, minute| {
+            NaiveDate::from_ymd_opt(2026, month, day)
+                .unwrap()
+                .and_hms_opt(hour, minute, 0)
+                .unwrap()
+        };
+
+        // 02:30 does not exist on 2026-03-29, an hour later it is 03:30 CEST.
+        assert_eq!(
+            localize(berlin, local(3, 29, 2, 30)),
+            utc(2026, 3, 29, 1, 30)
+        );
+
+        // 02:30 happens twice on 2026-10-25, first in CEST.
+        assert_eq!(
+            localize(berlin, local(10, 25, 2, 30)),
+            utc(2026, 10, 25, 0, 30)
+        );
+
+        assert_eq!(
+            localize(berlin, local(10, 26, 2, 30)),
+            utc(2026, 10, 26, 1, 30)
+        );
+    }
+}
@@ -1,14 +1,28 @@
 use crate::{
     api_error::ApiError,
-    todo::model::{CreateTodoForm, Todo, UpdateTodoForm},
+    auth::{
+        personal_access_token::{TODOS_READ, TODOS_WRITE},
+        Claims,
+    },
+    todo::model::{
+        CreateTodoForm, MoveTodoForm, SearchQuery, Todo, TodoQuery, TodoResponse,
+        TodoSearchResponse, UpcomingQuery, UpdateTodoForm,
+    },
     user::User,
 };
 use actix_web::{delete, get, patch, post, web, HttpResponse};
 use serde_json::json;
+use validator::Validate;
 
 #[post("/")]
-async fn create(user: User, form: web::Json<CreateTodoForm>) -> Result<HttpResponse, ApiError> {
-    let todo = Todo::create(user, form.into_inner())?;
+async fn create(
+    claims: Claims,
+    user: User,
+    form: web::Json<CreateTodoForm>,
+) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_WRITE)?;
+
+    let todo = TodoResponse::from_todo(Todo::create(user, form.into_inner())?)?;
 
     Ok(HttpResponse::Ok().json(json!({
         "message": "Todo created successfully",
@@ -17,26 +31,114 @@ async fn create(user: User, form: web::Json<CreateTodoForm>) -> Result<HttpRespo
 }
 
 #[get("/")]
-async fn todos(user: User) -> Result<HttpResponse, ApiError> {
-    let todos = Todo::todos(user)?;
+async fn todos(
+    claims: Claims,
+    user: User,
+    query: web::Query<TodoQuery>,
+) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_READ)?;
+
+    match query.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    let page = Todo::find_page(user.id, &query)?;
 
     Ok(HttpResponse::Ok().json(json!({
         "message": "Todos fetched successfully",
-        "data": todos
+        "data": TodoResponse::from_todos(page.todos)?,
+        "next_cursor": page.next_cursor,
+        "total": page.total
+    })))
+}
+
+#[get("/search")]
+async fn search(
+    claims: Claims,
+    user: User,
+    query: web::Query<SearchQuery>,
+) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_READ)?;
+
+    match query.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    let results = TodoSearchResponse::from_results(Todo::search(user.id, &query)?)?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Todos searched successfully",
+        "data": results
+    })))
+}
+
+#[get("/overdue")]
+async fn overdue(claims: Claims, user: User) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_READ)?;
+
+    let due = Todo::overdue(user)?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Overdue todos fetched successfully",
+        "data": TodoResponse::from_todos(due)?
+    })))
+}
+
+#[get("/today")]
+async fn today(claims: Claims, user: User) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_READ)?;
+
+    let due = Todo::due_today(user)?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Todos due today fetched successfully",
+        "data": TodoResponse::from_todos(due)?
+    })))
+}
+
+#[get("/upcoming")]
+async fn upcoming(
+    claims: Claims,
+    user: User,
+    query: web::Query<UpcomingQuery>,
+) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_READ)?;
+
+    match query.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    let due = Todo::upcoming(user, query.days.unwrap_or(7))?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Upcoming todos fetched successfully",
+        "data": TodoResponse::from_todos(due)?
     })))
 }
 
 #[patch("/{id}")]
 async fn update(
+    claims: Claims,
     user: User,
     todo: Todo,
     form: web::Json<UpdateTodoForm>,
 ) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_WRITE)?;
+
     if todo.user_id != user.id {
         return Err(ApiError::unauthorized("Unauthorized".to_string()));
     }
 
-    let todo = Todo::update(user, todo, form.into_inner())?;
+    let todo = TodoResponse::from_todo(Todo::update(user, todo, form.into_inner())?)?;
 
     Ok(HttpResponse::Ok().json(json!({
         "message": "Todo updated successfully",
@@ -45,7 +147,9 @@ async fn update(
 }
 
 #[delete("/{id}")]
-async fn delete(todo: Todo, user: User) -> Result<HttpResponse, ApiError> {
+async fn delete(claims: Claims, todo: Todo, user: User) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_WRITE)?;
+
     if todo.user_id != user.id {
         return Err(ApiError::unauthorized("Unauthorized".to_string()));
     }
@@ -58,13 +162,36 @@ async fn delete(todo: Todo, user: User) -> Result<HttpResponse, ApiError> {
     })))
 }
 
+#[post("/{id}/move")]
+async fn move_todo(
+    claims: Claims,
+    user: User,
+    todo: Todo,
+    form: web::Json<MoveTodoForm>,
+) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_WRITE)?;
+
+    if todo.user_id != user.id {
+        return Err(ApiError::unauthorized("Unauthorized".to_string()));
+    }
+
+    let todo = TodoResponse::from_todo(Todo::move_to(user, todo, form.into_inner())?)?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Todo moved successfully",
+        "data": todo
+    })))
+}
+
 #[patch("/done/{id}")]
-async fn done(todo: Todo, user: User) -> Result<HttpResponse, ApiError> {
+async fn done(claims: Claims, todo: Todo, user: User) -> Result<HttpResponse, ApiError> {
+    claims.require_scope(TODOS_WRITE)?;
+
     if todo.user_id != user.id {
         return Err(ApiError::unauthorized("Unauthorized".to_string()));
     }
 
-    let todo = Todo::toggle_completion(user, todo)?;
+    let (todo, next) = Todo::toggle_completion(user, todo)?;
 
     let message = if todo.done {
         "Todo marked as done"
@@ -74,13 +201,20 @@ async fn done(todo: Todo, user: User) -> Result<HttpResponse, ApiError> {
 
     Ok(HttpResponse::Ok().json(json!({
         "message": message,
-        "data": todo
+        "data": TodoResponse::from_todo(todo)?,
+        "next_occurrence": next.map(TodoResponse::from_todo).transpose()?
     })))
 }
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(create);
     cfg.service(todos);
+    cfg.service(search);
+    cfg.service(overdue);
+    cfg.service(today);
+    cfg.service(upcoming);
     cfg.service(update);
     cfg.service(delete);
+    cfg.service(move_todo);
+    cfg.service(done);
 }
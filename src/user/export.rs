@@ -0,0 +1,348 @@
+use std::io::{Cursor, Write};
+
+use actix_web::{rt, web};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{Binary, Nullable};
+use log::error;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+use crate::{
+    api_error::ApiError,
+    audit::AuditEvent,
+    auth::session::Session,
+    db,
+    project::{
+        model::{ProjectQuery, ProjectResponse},
+        Project,
+    },
+    schema::*,
+    settings::setting,
+    tag::Tag,
+    todo::model::{Todo, TodoResponse},
+};
+
+use super::{model::UserResponse, User};
+
+pub const PENDING: &str = "pending";
+pub const COMPLETED: &str = "completed";
+pub const FAILED: &str = "failed";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Zip,
+}
+
+impl ExportFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Zip => "zip",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Zip => "application/zip",
+        }
+    }
+
+    /// Name offered to the browser when the export is downloaded.
+    pub fn file_name(&self) -> String {
+        format!(
+            "todo-export-{}.{}",
+            Utc::now().format("%Y%m%d"),
+            self.as_str()
+        )
+    }
+
+    fn parse(format: &str) -> Self {
+        match format {
+            "zip" => ExportFormat::Zip,
+            _ => ExportFormat::Json,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// Everything stored about a user, as handed out on a data access request.
+#[derive(Serialize)]
+struct ExportDocument {
+    exported_at: NaiveDateTime,
+    profile: UserResponse,
+    todos: Vec<TodoResponse>,
+    projects: Vec<ProjectResponse>,
+    tags: Vec<Tag>,
+    sessions: Vec<Session>,
+    audit_events: Vec<AuditEvent>,
+}
+
+/// An export generated in the background for accounts too large to export
+/// within a request. The archive is kept until `expires_at`.
+#[derive(Serialize, Queryable, Insertable, Debug)]
+#[table_name = "data_export"]
+pub struct DataExport {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub user_id: Uuid,
+    pub format: String,
+    pub status: String,
+    #[serde(skip_serializing)]
+    pub content: Option<Vec<u8>>,
+    pub error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub completed_at: Option<NaiveDateTime>,
+    pub expires_at: NaiveDateTime,
+}
+
+impl DataExport {
+    /// Unexpired export `id` of `user_id`, leaving out its content.
+    pub fn find(user_id: Uuid, id: Uuid) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+        fail_stale(&mut conn, user_id)?;
+
+        let data_export = data_export::table
+            .filter(data_export::user_id.eq(user_id))
+            .filter(data_export::id.eq(id))
+            .filter(data_export::expires_at.gt(Utc::now().naive_utc()))
+            .select((
+                data_export::id,
+                data_export::user_id,
+                data_export::format,
+                data_export::status,
+                None::<Vec<u8>>.into_sql::<Nullable<Binary>>(),
+                data_export::error,
+                data_export::created_at,
+                data_export::completed_at,
+                data_export::expires_at,
+            ))
+            .first(&mut conn)?;
+
+        Ok(data_export)
+    }
+
+    /// Unexpired export `id` of `user_id`, along with its content.
+    pub fn download(user_id: Uuid, id: Uuid) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+        fail_stale(&mut conn, user_id)?;
+
+        let data_export = data_export::table
+            .filter(data_export::user_id.eq(user_id))
+            .filter(data_export::id.eq(id))
+            .filter(data_export::expires_at.gt(Utc::now().naive_utc()))
+            .first(&mut conn)?;
+
+        Ok(data_export)
+    }
+
+    /// Queues an export of `user_id` and generates it in the background.
+    ///
+    /// An export of the same format that is still being generated is returned
+    /// instead of starting another one.
+    pub fn start(user_id: Uuid, format: ExportFormat) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+        let now = Utc::now().naive_utc();
+
+        diesel::delete(data_export::table)
+            .filter(data_export::expires_at.le(now))
+            .execute(&mut conn)?;
+
+        fail_stale(&mut conn, user_id)?;
+
+        let pending = data_export::table
+            .filter(data_export::user_id.eq(user_id))
+            .filter(data_export::format.eq(format.as_str()))
+            .filter(data_export::status.eq(PENDING))
+            .first::<DataExport>(&mut conn)
+            .optional()?;
+
+        if let Some(pending) = pending {
+            return Ok(pending);
+        }
+
+        let data_export = DataExport {
+            id: Uuid::new_v4(),
+            user_id,
+            format: format.as_str().to_string(),
+            status: PENDING.to_string(),
+            content: None,
+            error: None,
+            created_at: now,
+            completed_at: None,
+            expires_at: now + ttl(),
+        };
+
+        let data_export: DataExport = diesel::insert_into(data_export::table)
+            .values(data_export)
+            .get_result(&mut conn)?;
+
+        let id = data_export.id;
+
+        rt::spawn(async move {
+            let result = web::block(move || Self::generate(id, user_id, format)).await;
+
+            match result {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) => error!("Failed generating data export {}: {}", id, e),
+                Err(e) => error!("Failed generating data export {}: {}", id, e),
+            }
+        });
+
+        Ok(data_export)
+    }
+
+    pub fn export_format(&self) -> ExportFormat {
+        ExportFormat::parse(&self.format)
+    }
+
+    fn generate(id: Uuid, user_id: Uuid, format: ExportFormat) -> Result<(), ApiError> {
+        let (status, content, error) = match build(user_id, format) {
+            Ok(content) => (COMPLETED, Some(content), None),
+            Err(e) => {
+                error!("Failed building data export {}: {}", id, e);
+                (
+                    FAILED,
+                    None,
+                    Some("The export could not be generated".to_string()),
+                )
+            }
+        };
+
+        let mut conn = db::connection()?;
+
+        diesel::update(data_export::table)
+            .filter(data_export::id.eq(id))
+            .set((
+                data_export::status.eq(status),
+                data_export::content.eq(content),
+                data_export::error.eq(error),
+                data_export::completed_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+}
+
+/// Marks exports of `user_id` that have been pending for longer than
+/// `EXPORT_TIMEOUT_MINUTES` as failed. Their generator was most likely lost
+/// with a restart, and they would otherwise keep new exports from starting.
+fn fail_stale(conn: &mut PgConnection, user_id: Uuid) -> Result<(), ApiError> {
+    diesel::update(data_export::table)
+        .filter(data_export::user_id.eq(user_id))
+        .filter(data_export::status.eq(PENDING))
+        .filter(data_export::created_at.le(Utc::now().naive_utc() - timeout()))
+        .set((
+            data_export::status.eq(FAILED),
+            data_export::error.eq("The export timed out, please request a new one"),
+            data_export::completed_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Whether `user_id` has few enough todos to be exported within the request.
+pub fn exports_immediately(user_id: Uuid) -> Result<bool, ApiError> {
+    let mut conn = db::connection()?;
+
+    let todos: i64 = todo::table
+        .filter(todo::user_id.eq(user_id))
+        .count()
+        .get_result(&mut conn)?;
+
+    let max_todos = setting("EXPORT_SYNC_MAX_TODOS", 500);
+
+    Ok(todos <= max_todos)
+}
+
+/// Collects the data of `user_id` into a single JSON document, or a zip
+/// archive with one JSON file per kind of record.
+pub fn build(user_id: Uuid, format: ExportFormat) -> Result<Vec<u8>, ApiError> {
+    let user = User::find(user_id)?;
+
+    let document = ExportDocument {
+        exported_at: Utc::now().naive_utc(),
+        profile: UserResponse::from(user.clone()),
+        todos: TodoResponse::from_todos(Todo::todos(user)?)?,
+        projects: [false, true]
+            .into_iter()
+            .map(|archived| Project::find_all(user_id, &ProjectQuery { archived }))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect(),
+        tags: Tag::find_all(user_id)?,
+        sessions: Session::find_all(user_id)?,
+        audit_events: AuditEvent::find_all(user_id)?,
+    };
+
+    match format {
+        ExportFormat::Json => to_json(&document),
+        ExportFormat::Zip => {
+            let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+
+            add_file(&mut archive, "profile.json", &document.profile)?;
+            add_file(&mut archive, "todos.json", &document.todos)?;
+            add_file(&mut archive, "projects.json", &document.projects)?;
+            add_file(&mut archive, "tags.json", &document.tags)?;
+            add_file(&mut archive, "sessions.json", &document.sessions)?;
+            add_file(&mut archive, "audit_events.json", &document.audit_events)?;
+
+            let archive = archive.finish().map_err(|e| {
+                error!("Failed finishing export archive: {}", e);
+                ApiError::internal_server_error()
+            })?;
+
+            Ok(archive.into_inner())
+        }
+    }
+}
+
+fn add_file<T: Serialize>(
+    archive: &mut ZipWriter<Cursor<Vec<u8>>>,
+    name: &str,
+    value: &T,
+) -> Result<(), ApiError> {
+    let content = to_json(value)?;
+
+    archive
+        .start_file(name, SimpleFileOptions::default())
+        .and_then(|_| archive.write_all(&content).map_err(Into::into))
+        .map_err(|e| {
+            error!("Failed writing {} to export archive: {}", name, e);
+            ApiError::internal_server_error()
+        })
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Vec<u8>, ApiError> {
+    serde_json::to_vec_pretty(value).map_err(|e| {
+        error!("Failed serializing data export: {}", e);
+        ApiError::internal_server_error()
+    })
+}
+
+/// How long a generated export can be downloaded.
+fn ttl() -> Duration {
+    let hours = setting("EXPORT_EXPIRY_HOURS", 24);
+
+    Duration::hours(hours)
+}
+
+/// How long an export may take to generate before it is given up on.
+fn timeout() -> Duration {
+    let minutes = setting("EXPORT_TIMEOUT_MINUTES", 30);
+
+    Duration::minutes(minutes)
+}
@@ -1,5 +1,9 @@
+pub mod export;
 pub mod model;
+pub mod purge;
+pub mod role;
 pub mod routes;
 
 pub use model::User;
+pub use role::Role;
 pub use routes::init_routes;
@@ -1,20 +1,24 @@
 use crate::api_error::ApiError;
 use crate::auth::models::RegisterForm;
-use crate::auth::verify;
+use crate::auth::{check_session, logout_everywhere, password::hasher, Claims};
 use crate::db;
 use crate::schema::*;
+use crate::settings::setting;
+use crate::user::Role;
 use actix_web::FromRequest;
-use bcrypt::BcryptError;
+use chrono::Duration;
 use chrono::NaiveDateTime;
 use chrono::Utc;
+use chrono_tz::Tz;
 use diesel::prelude::*;
 use futures::future::LocalBoxFuture;
 use log::error;
 use serde::{Deserialize, Serialize};
 
 use uuid::Uuid;
+use validator::{Validate, ValidationError};
 
-#[derive(Serialize, Deserialize, Queryable, Insertable, Debug)]
+#[derive(Queryable, Insertable, Clone, Debug)]
 #[table_name = "user"]
 pub struct User {
     pub id: Uuid,
@@ -23,6 +27,17 @@ pub struct User {
     pub password: String,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
+    pub email_verified_at: Option<NaiveDateTime>,
+    pub account_locked_until: Option<NaiveDateTime>,
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<NaiveDateTime>,
+    pub role: Role,
+    pub deactivated_at: Option<NaiveDateTime>,
+    pub deleted_at: Option<NaiveDateTime>,
+    /// IANA name of the timezone days are counted in, such as `Europe/Berlin`.
+    pub timezone: String,
+    /// Time step of the last TOTP code accepted, which can not be used again.
+    pub totp_last_step: Option<i64>,
 }
 
 impl User {
@@ -45,7 +60,7 @@ impl User {
     pub fn create(user: RegisterForm) -> Result<Self, ApiError> {
         let mut conn = db::connection()?;
 
-        let user = User::from(user);
+        let user = User::try_from(user)?;
         let user = diesel::insert_into(user::table)
             .values(user)
             .get_result(&mut conn)?;
@@ -53,25 +68,127 @@ impl User {
         Ok(user)
     }
 
-    pub fn update(id: Uuid, user: RegisterForm) -> Result<Self, ApiError> {
+    pub fn update(id: Uuid, user: UpdateUserForm) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        if user.name.is_none() {
+            return Err(ApiError::bad_request("Nothing to update".to_string()));
+        }
+
+        let user = diesel::update(user::table)
+            .filter(user::id.eq(id))
+            .set((user, user::updated_at.eq(Utc::now().naive_utc())))
+            .get_result(&mut conn)?;
+
+        Ok(user)
+    }
+
+    pub fn update_profile(id: Uuid, profile: UpdateProfileForm) -> Result<Self, ApiError> {
         let mut conn = db::connection()?;
 
         let user = diesel::update(user::table)
             .filter(user::id.eq(id))
-            .set(user)
+            .set((profile, user::updated_at.eq(Utc::now().naive_utc())))
             .get_result(&mut conn)?;
 
         Ok(user)
     }
 
-    pub fn delete(id: Uuid) -> Result<usize, ApiError> {
+    /// Schedules the user for deletion and signs them out everywhere.
+    ///
+    /// The account can be restored until the grace period is over and
+    /// [`User::purge_deleted`] removes it along with everything it owns.
+    pub fn delete(id: Uuid) -> Result<Self, ApiError> {
         let mut conn = db::connection()?;
 
-        let res = diesel::delete(user::table.filter(user::id.eq(id))).execute(&mut conn)?;
+        let user = diesel::update(user::table)
+            .filter(user::id.eq(id))
+            .filter(user::deleted_at.is_null())
+            .set(user::deleted_at.eq(Utc::now().naive_utc()))
+            .get_result(&mut conn)?;
+
+        logout_everywhere(id)?;
+
+        Ok(user)
+    }
+
+    /// Cancels the deletion of a user whose grace period is not over yet.
+    pub fn restore(id: Uuid) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let user = diesel::update(user::table)
+            .filter(user::id.eq(id))
+            .filter(user::deleted_at.gt(Utc::now().naive_utc() - deletion_grace_period()))
+            .set((
+                user::deleted_at.eq(None::<NaiveDateTime>),
+                user::updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .get_result(&mut conn)?;
+
+        Ok(user)
+    }
+
+    /// Permanently deletes users whose grace period is over. Their todos and
+    /// tokens go with them through `ON DELETE CASCADE`.
+    pub fn purge_deleted() -> Result<usize, ApiError> {
+        let mut conn = db::connection()?;
+
+        let res = diesel::delete(user::table)
+            .filter(user::deleted_at.lt(Utc::now().naive_utc() - deletion_grace_period()))
+            .execute(&mut conn)?;
 
         Ok(res)
     }
 
+    /// Blocks or unblocks the user, who is signed out everywhere when blocked.
+    pub fn set_deactivated(id: Uuid, deactivated: bool) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let now = Utc::now().naive_utc();
+
+        let user = diesel::update(user::table)
+            .filter(user::id.eq(id))
+            .set((
+                user::deactivated_at.eq(Some(now).filter(|_| deactivated)),
+                user::updated_at.eq(now),
+            ))
+            .get_result(&mut conn)?;
+
+        if deactivated {
+            logout_everywhere(id)?;
+        }
+
+        Ok(user)
+    }
+
+    /// When a deleted user will be removed for good.
+    pub fn purge_at(&self) -> Option<NaiveDateTime> {
+        self.deleted_at
+            .map(|deleted_at| deleted_at + deletion_grace_period())
+    }
+
+    pub fn tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+
+    /// Rejects users that were deactivated or deleted.
+    pub fn ensure_active(&self) -> Result<(), ApiError> {
+        if let Some(purge_at) = self.purge_at() {
+            return Err(ApiError::forbidden(format!(
+                "Account has been deleted, it can be restored until {}",
+                purge_at.format("%Y-%m-%d %H:%M:%S UTC")
+            )));
+        }
+
+        if self.deactivated_at.is_some() {
+            return Err(ApiError::forbidden(
+                "Account has been deactivated".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
     pub fn find_by_email(email: &str) -> Result<Self, ApiError> {
         let mut conn = db::connection()?;
 
@@ -79,22 +196,243 @@ impl User {
         Ok(user)
     }
 
-    pub fn verify_password(&self, password: &str) -> Result<bool, BcryptError> {
-        let is_valid = bcrypt::verify(password, &self.password)?;
-        Ok(is_valid)
+    pub fn update_password(id: Uuid, password: &str) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let user = diesel::update(user::table)
+            .filter(user::id.eq(id))
+            .set((
+                user::password.eq(User::hash_password(password)?),
+                user::updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .get_result(&mut conn)?;
+
+        Ok(user)
     }
+
+    /// Changes the email of the user, who has to verify the new address.
+    pub fn update_email(id: Uuid, email: &str) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let user = diesel::update(user::table)
+            .filter(user::id.eq(id))
+            .set((
+                user::email.eq(email),
+                user::email_verified_at.eq(None::<NaiveDateTime>),
+                user::updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .get_result(&mut conn)?;
+
+        Ok(user)
+    }
+
+    pub fn mark_email_verified(id: Uuid) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let user = diesel::update(user::table)
+            .filter(user::id.eq(id))
+            .set(user::email_verified_at.eq(Utc::now().naive_utc()))
+            .get_result(&mut conn)?;
+
+        Ok(user)
+    }
+
+    /// Blocks logins until `locked_until`, or lifts the lock when `None`.
+    pub fn set_locked_until(
+        id: Uuid,
+        locked_until: Option<NaiveDateTime>,
+    ) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let user = diesel::update(user::table)
+            .filter(user::id.eq(id))
+            .set(user::account_locked_until.eq(locked_until))
+            .get_result(&mut conn)?;
+
+        Ok(user)
+    }
+
+    /// Stores the TOTP secret of the user. Two-factor authentication is only
+    /// enforced once `enabled_at` is set.
+    pub fn set_totp(
+        id: Uuid,
+        secret: Option<String>,
+        enabled_at: Option<NaiveDateTime>,
+    ) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let user = diesel::update(user::table)
+            .filter(user::id.eq(id))
+            .set((
+                user::totp_secret.eq(secret),
+                user::totp_enabled_at.eq(enabled_at),
+                user::updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .get_result(&mut conn)?;
+
+        Ok(user)
+    }
+
+    /// Makes the verified accounts among `emails` admins, returning the ones
+    /// that were not admins yet.
+    pub fn promote_admins(emails: &[String]) -> Result<Vec<Self>, ApiError> {
+        let mut conn = db::connection()?;
+
+        let users = diesel::update(user::table)
+            .filter(user::email.eq_any(emails))
+            .filter(user::email_verified_at.is_not_null())
+            .filter(user::role.ne(Role::Admin))
+            .set((
+                user::role.eq(Role::Admin),
+                user::updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .get_results(&mut conn)?;
+
+        Ok(users)
+    }
+
+    /// Records that the TOTP code of time step `step` was used. Returns `false`
+    /// if a code of that step or a later one was accepted before.
+    pub fn use_totp_step(id: Uuid, step: i64) -> Result<bool, ApiError> {
+        let mut conn = db::connection()?;
+
+        let updated = diesel::update(user::table)
+            .filter(user::id.eq(id))
+            .filter(
+                user::totp_last_step
+                    .is_null()
+                    .or(user::totp_last_step.lt(step)),
+            )
+            .set(user::totp_last_step.eq(step))
+            .execute(&mut conn)?;
+
+        Ok(updated == 1)
+    }
+
+    pub fn set_role(id: Uuid, role: Role) -> Result<Self, ApiError> {
+        let mut conn = db::connection()?;
+
+        let user = diesel::update(user::table)
+            .filter(user::id.eq(id))
+            .set((
+                user::role.eq(role),
+                user::updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .get_result(&mut conn)?;
+
+        Ok(user)
+    }
+
+    pub fn hash_password(password: &str) -> Result<String, ApiError> {
+        hasher().hash(password).map_err(|e| {
+            error!("{}", e);
+            ApiError::internal_server_error()
+        })
+    }
+
+    pub fn verify_password(&self, password: &str) -> Result<bool, ApiError> {
+        hasher().verify(password, &self.password).map_err(|e| {
+            error!("{}", e);
+            ApiError::internal_server_error()
+        })
+    }
+
+    /// Whether the stored hash should be upgraded to the configured hasher.
+    pub fn needs_password_rehash(&self) -> bool {
+        hasher().needs_rehash(&self.password)
+    }
+}
+
+/// What API responses show of a user. Credentials and security state stay out of it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UserResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub role: Role,
+    pub email_verified_at: Option<NaiveDateTime>,
+    pub totp_enabled: bool,
+    pub deactivated_at: Option<NaiveDateTime>,
+    pub deleted_at: Option<NaiveDateTime>,
+    pub timezone: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<User> for UserResponse {
+    fn from(user: User) -> Self {
+        UserResponse {
+            id: user.id,
+            name: user.name,
+            email: user.email,
+            role: user.role,
+            email_verified_at: user.email_verified_at,
+            totp_enabled: user.totp_enabled_at.is_some(),
+            deactivated_at: user.deactivated_at,
+            deleted_at: user.deleted_at,
+            timezone: user.timezone,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+/// Fields users can change on their own profile.
+#[derive(Serialize, Deserialize, AsChangeset, Validate)]
+#[table_name = "user"]
+pub struct UpdateProfileForm {
+    #[validate(length(min = 1, message = "Name can not be empty"))]
+    pub name: Option<String>,
+    #[validate(custom = "validate_timezone")]
+    pub timezone: Option<String>,
 }
 
-impl From<RegisterForm> for User {
-    fn from(user: RegisterForm) -> Self {
-        User {
+fn validate_timezone(timezone: &str) -> Result<(), ValidationError> {
+    if timezone.parse::<Tz>().is_err() {
+        let mut error = ValidationError::new("timezone");
+        error.message = Some("Unknown timezone, expected a name such as Europe/Berlin".into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Fields of a user that can be changed directly. Credentials and the email
+/// address, which has to be verified again, have dedicated endpoints under `/auth`.
+#[derive(Serialize, Deserialize, AsChangeset, Validate)]
+#[table_name = "user"]
+pub struct UpdateUserForm {
+    #[validate(length(min = 1, message = "Name can not be empty"))]
+    pub name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct UpdateRoleForm {
+    #[validate(required(message = "Role is required"))]
+    pub role: Option<Role>,
+}
+
+impl TryFrom<RegisterForm> for User {
+    type Error = ApiError;
+
+    fn try_from(user: RegisterForm) -> Result<Self, Self::Error> {
+        Ok(User {
             id: Uuid::new_v4(),
             email: user.email.unwrap(),
-            password: bcrypt::hash(&user.password.unwrap(), 4).unwrap(),
+            password: User::hash_password(&user.password.unwrap())?,
             name: user.name.unwrap(),
             created_at: Utc::now().naive_utc(),
             updated_at: None,
-        }
+            email_verified_at: None,
+            account_locked_until: None,
+            totp_secret: None,
+            totp_enabled_at: None,
+            role: Role::User,
+            deactivated_at: None,
+            deleted_at: None,
+            timezone: "UTC".to_string(),
+            totp_last_step: None,
+        })
     }
 }
 
@@ -104,49 +442,30 @@ impl FromRequest for User {
 
     fn from_request(
         req: &actix_web::HttpRequest,
-        _payload: &mut actix_web::dev::Payload,
+        payload: &mut actix_web::dev::Payload,
     ) -> Self::Future {
-        let authorization = req.headers().get("Authorization");
+        let claims = Claims::from_request(req, payload);
+
+        Box::pin(async move {
+            let claims = claims.await?;
 
-        let token = match authorization {
-            Some(token) => token.to_str().map_err(|_| {
-                error!("Token is not a valid string");
+            check_session(&claims)?;
+
+            let user = User::find(claims.sub).map_err(|e| {
+                error!("{}", e);
                 ApiError::unauthorized("This request is unauthorized".to_string())
-            }),
-            None => {
-                return Box::pin(async {
-                    error!("No token provided");
-                    Err(ApiError::unauthorized(
-                        "This request is unauthorized".to_string(),
-                    ))
-                })
-            }
-        };
-
-        let verify = verify(token.unwrap());
-
-        let claims = match verify {
-            Ok(claims) => claims,
-            Err(e) => {
-                error!("{:?}", e);
-                return Box::pin(async {
-                    Err(ApiError::unauthorized(
-                        "This request is unauthorized".to_string(),
-                    ))
-                });
-            }
-        };
-
-        let user = User::find(
-            Uuid::parse_str(&claims.get("id").unwrap())
-                .map_err(|e| {
-                    error!("{}", e);
-                    ApiError::unauthorized("This request is unauthorized".to_string())
-                })
-                .unwrap(),
-        )
-        .unwrap();
-
-        Box::pin(async { Ok(user) })
+            })?;
+
+            user.ensure_active()?;
+
+            Ok(user)
+        })
     }
 }
+
+/// How long a deleted account can be restored before it is purged.
+fn deletion_grace_period() -> Duration {
+    let days = setting("ACCOUNT_DELETION_GRACE_DAYS", 30);
+
+    Duration::days(days)
+}
@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+use actix_web::{rt, web};
+use log::{error, info};
+
+use crate::settings::setting;
+
+use super::User;
+
+/// Periodically purges accounts whose deletion grace period is over.
+pub fn spawn() {
+    // A zero interval would make the timer panic.
+    let seconds = match setting("ACCOUNT_PURGE_INTERVAL_SECONDS", 60 * 60) {
+        0 => 60 * 60,
+        seconds => seconds,
+    };
+
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(Duration::from_secs(seconds));
+
+        loop {
+            interval.tick().await;
+
+            match web::block(User::purge_deleted).await {
+                Ok(Ok(0)) => (),
+                Ok(Ok(purged)) => info!("Purged {} deleted accounts", purged),
+                Ok(Err(e)) => error!("Failed purging deleted accounts: {}", e),
+                Err(e) => error!("Failed purging deleted accounts: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,76 @@
+use std::{env, fmt};
+
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    serialize::{self, Output, ToSql},
+    sql_types::Text,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use super::User;
+
+/// What a user is allowed to do. Stored as text in `user.role`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl ToSql<Text, Pg> for Role {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for Role {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "user" => Ok(Role::User),
+            "admin" => Ok(Role::Admin),
+            role => Err(format!("Unknown role {}", role).into()),
+        }
+    }
+}
+
+/// Makes the accounts listed in `ADMIN_EMAILS` admins, so that the first admin
+/// does not have to be created by hand. Only verified accounts are promoted, so
+/// nobody can claim the role by registering one of the addresses first.
+pub fn bootstrap_admins() {
+    let emails = env::var("ADMIN_EMAILS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|email| !email.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    if emails.is_empty() {
+        return;
+    }
+
+    let users = User::promote_admins(&emails).expect("Failed promoting ADMIN_EMAILS");
+
+    for user in users {
+        info!("Made {} an admin", user.email);
+    }
+}
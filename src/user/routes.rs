@@ -1,39 +1,220 @@
-use actix_web::{delete, get, put, web, HttpResponse};
+use actix_web::{
+    delete, get,
+    http::header::{ContentDisposition, LOCATION},
+    patch, post, put, web, HttpResponse,
+};
 use serde_json::json;
 use uuid::Uuid;
 
-use crate::{api_error::ApiError, user::model::User, auth::models::RegisterForm};
+use validator::Validate;
 
-#[get("/users")]
-async fn find_all() -> Result<HttpResponse, ApiError> {
-    let users = User::find_all()?;
+use crate::{
+    api_error::ApiError,
+    auth::{logout_everywhere, Admin, RequireRole},
+    user::export::{self, DataExport, ExportQuery, COMPLETED, PENDING},
+    user::model::{UpdateProfileForm, UpdateRoleForm, UpdateUserForm, User, UserResponse},
+};
+
+#[get("/me")]
+async fn me(user: User) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(UserResponse::from(user)))
+}
+
+#[patch("/me")]
+async fn update_me(
+    user: User,
+    form: web::Json<UpdateProfileForm>,
+) -> Result<HttpResponse, ApiError> {
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    let user = User::update_profile(user.id, form.into_inner())?;
+    Ok(HttpResponse::Ok().json(UserResponse::from(user)))
+}
+
+#[delete("/me")]
+async fn delete_me(user: User) -> Result<HttpResponse, ApiError> {
+    let user = User::delete(user.id)?;
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Account deleted, it can be restored until it is purged",
+        "purge_at": user.purge_at(),
+    })))
+}
+
+/// Exports everything stored about the user. Large accounts are exported in
+/// the background, the response then points to the status of the export.
+#[get("/me/export")]
+async fn export_me(user: User, query: web::Query<ExportQuery>) -> Result<HttpResponse, ApiError> {
+    let format = query.format;
+
+    if export::exports_immediately(user.id)? {
+        let content = web::block(move || export::build(user.id, format))
+            .await
+            .map_err(|_| ApiError::internal_server_error())??;
+
+        return Ok(HttpResponse::Ok()
+            .content_type(format.content_type())
+            .insert_header(ContentDisposition::attachment(format.file_name()))
+            .body(content));
+    }
+
+    let data_export = DataExport::start(user.id, format)?;
+
+    Ok(HttpResponse::Accepted()
+        .insert_header((LOCATION, format!("/users/me/exports/{}", data_export.id)))
+        .json(json!({
+            "message": "Export started, poll its status until it is completed",
+            "export": data_export,
+        })))
+}
+
+#[get("/me/exports/{id}")]
+async fn find_export(user: User, id: web::Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    let data_export = DataExport::find(user.id, id.into_inner())?;
+    Ok(HttpResponse::Ok().json(data_export))
+}
+
+#[get("/me/exports/{id}/download")]
+async fn download_export(user: User, id: web::Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    let data_export = DataExport::download(user.id, id.into_inner())?;
+    let format = data_export.export_format();
+
+    match (data_export.status.as_str(), data_export.content) {
+        (COMPLETED, Some(content)) => Ok(HttpResponse::Ok()
+            .content_type(format.content_type())
+            .insert_header(ContentDisposition::attachment(format.file_name()))
+            .body(content)),
+        (PENDING, _) => Err(ApiError::new(
+            409,
+            "Export is still being generated".to_string(),
+        )),
+        _ => Err(ApiError::new(
+            409,
+            data_export
+                .error
+                .unwrap_or_else(|| "Export failed".to_string()),
+        )),
+    }
+}
+
+#[get("")]
+async fn find_all(_: RequireRole<Admin>) -> Result<HttpResponse, ApiError> {
+    let users: Vec<UserResponse> = User::find_all()?
+        .into_iter()
+        .map(UserResponse::from)
+        .collect();
     Ok(HttpResponse::Ok().json(users))
 }
 
-#[get("/users/{id}")]
-async fn find(id: web::Path<Uuid>) -> Result<HttpResponse, ApiError> {
+#[get("/{id}")]
+async fn find(_: RequireRole<Admin>, id: web::Path<Uuid>) -> Result<HttpResponse, ApiError> {
     let user = User::find(id.into_inner())?;
-    Ok(HttpResponse::Ok().json(user))
+    Ok(HttpResponse::Ok().json(UserResponse::from(user)))
 }
 
-#[put("/users/{id}")]
+#[put("/{id}")]
 async fn update(
+    _: RequireRole<Admin>,
     id: web::Path<Uuid>,
-    user: web::Json<RegisterForm>,
+    user: web::Json<UpdateUserForm>,
 ) -> Result<HttpResponse, ApiError> {
+    match user.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
     let user = User::update(id.into_inner(), user.into_inner())?;
-    Ok(HttpResponse::Ok().json(user))
+    Ok(HttpResponse::Ok().json(UserResponse::from(user)))
+}
+
+#[delete("/{id}")]
+async fn delete(_: RequireRole<Admin>, id: web::Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    let user = User::delete(id.into_inner())?;
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "User deleted, it can be restored until it is purged",
+        "purge_at": user.purge_at(),
+    })))
+}
+
+#[post("/{id}/restore")]
+async fn restore(_: RequireRole<Admin>, id: web::Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    let user = User::restore(id.into_inner())?;
+    Ok(HttpResponse::Ok().json(UserResponse::from(user)))
+}
+
+#[post("/{id}/deactivate")]
+async fn deactivate(
+    admin: RequireRole<Admin>,
+    id: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let id = id.into_inner();
+
+    if id == admin.claims.sub {
+        return Err(ApiError::forbidden(
+            "You can not deactivate your own account".to_string(),
+        ));
+    }
+
+    let user = User::set_deactivated(id, true)?;
+    Ok(HttpResponse::Ok().json(UserResponse::from(user)))
+}
+
+#[post("/{id}/reactivate")]
+async fn reactivate(_: RequireRole<Admin>, id: web::Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    let user = User::set_deactivated(id.into_inner(), false)?;
+    Ok(HttpResponse::Ok().json(UserResponse::from(user)))
 }
 
-#[delete("/users/{id}")]
-async fn delete(id: web::Path<Uuid>) -> Result<HttpResponse, ApiError> {
-    let num_deleted = User::delete(id.into_inner())?;
-    Ok(HttpResponse::Ok().json(json!({ "deleted": num_deleted })))
+#[put("/{id}/role")]
+async fn update_role(
+    admin: RequireRole<Admin>,
+    id: web::Path<Uuid>,
+    form: web::Json<UpdateRoleForm>,
+) -> Result<HttpResponse, ApiError> {
+    match form.validate() {
+        Ok(_) => (),
+        Err(e) => {
+            return Err(ApiError::bad_request(e.to_string()));
+        }
+    }
+
+    let id = id.into_inner();
+
+    // Keeps the last admin from locking everyone out.
+    if id == admin.claims.sub {
+        return Err(ApiError::forbidden(
+            "You can not change your own role".to_string(),
+        ));
+    }
+
+    let user = User::set_role(id, form.role.unwrap())?;
+
+    // Tokens carry the old role until they expire.
+    logout_everywhere(user.id)?;
+
+    Ok(HttpResponse::Ok().json(UserResponse::from(user)))
 }
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    // Registered before `/{id}`, which would match `me` as well.
+    cfg.service(me);
+    cfg.service(update_me);
+    cfg.service(delete_me);
+    cfg.service(export_me);
+    cfg.service(find_export);
+    cfg.service(download_export);
     cfg.service(find_all);
     cfg.service(find);
     cfg.service(update);
     cfg.service(delete);
+    cfg.service(update_role);
+    cfg.service(restore);
+    cfg.service(deactivate);
+    cfg.service(reactivate);
 }